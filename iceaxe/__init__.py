@@ -1,4 +1,4 @@
-from .alias_values import alias as alias
+from .alias_values import alias as alias, alias_table as alias_table
 from .base import (
     IndexConstraint as IndexConstraint,
     TableBase as TableBase,
@@ -0,0 +1,354 @@
+from json import dumps as json_dumps, loads as json_loads
+from sys import getsizeof
+from time import monotonic_ns
+from tracemalloc import get_traced_memory, start as start_tracing, stop as stop_tracing
+from typing import Any, Callable
+
+import pytest
+
+from iceaxe.base import TableBase
+from iceaxe.field import Field
+from iceaxe.functions import func
+from iceaxe.logging import CONSOLE, LOGGER
+from iceaxe.queries import QueryBuilder
+from iceaxe.session_optimized import optimize_exec_casting, optimize_exec_iter
+from iceaxe.typing import is_base_table, is_column, is_function_metadata
+
+WIDE_TABLE_COLUMNS = 30
+
+# Rows, fields to select, and a baseline that builds the same results by hand
+Scenario = tuple[list[Any], tuple[Any, ...], Callable[[], Any]]
+
+
+class WideDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    col_0: str
+    col_1: str
+    col_2: str
+    col_3: str
+    col_4: str
+    col_5: str
+    col_6: str
+    col_7: str
+    col_8: str
+    col_9: str
+    col_10: int
+    col_11: int
+    col_12: int
+    col_13: int
+    col_14: int
+    col_15: int
+    col_16: int
+    col_17: int
+    col_18: int
+    col_19: int
+    col_20: str
+    col_21: str
+    col_22: str
+    col_23: str
+    col_24: str
+    col_25: int
+    col_26: int
+    col_27: int
+    col_28: int
+    col_29: int
+
+
+class JsonHeavyDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    settings: dict[str, Any] = Field(is_json=True)
+    tags: list[str] = Field(is_json=True)
+    profile: dict[str, Any] = Field(is_json=True)
+
+
+class CompactDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    score: float
+
+
+def build_wide_row(i: int) -> dict[str, Any]:
+    row: dict[str, Any] = {"widedemo_id": i}
+    for column in range(WIDE_TABLE_COLUMNS):
+        row[f"widedemo_col_{column}"] = (
+            f"value {i}" if column < 10 or 20 <= column < 25 else i
+        )
+    return row
+
+
+def build_selects(*fields: Any) -> tuple[list[Any], list[tuple[bool, bool, bool]]]:
+    query = QueryBuilder().select(fields)
+    select_types = [
+        (
+            is_base_table(select_raw),
+            is_column(select_raw),
+            is_function_metadata(select_raw),
+        )
+        for select_raw in query._select_raw
+    ]
+    return query._select_raw, select_types
+
+
+def build_json_row(i: int) -> dict[str, Any]:
+    return {
+        "jsonheavydemo_id": i,
+        "jsonheavydemo_settings": json_dumps({"theme": "dark", "index": i}),
+        "jsonheavydemo_tags": json_dumps([f"tag-{i}", "common"]),
+        "jsonheavydemo_profile": json_dumps({"name": f"User {i}", "age": i % 100}),
+    }
+
+
+def time_callable(run: Callable[[], Any], num_loops: int) -> float:
+    start_time = monotonic_ns()
+    for _ in range(num_loops):
+        run()
+    return (monotonic_ns() - start_time) / num_loops / 1e9
+
+
+@pytest.mark.integration_tests
+def test_benchmark_batched_json_parsing():
+    num_rows = 20_000
+    num_loops = 10
+
+    select_raw, select_types = build_selects(JsonHeavyDemo)
+    values = [
+        {
+            "jsonheavydemo_id": i,
+            "jsonheavydemo_settings": json_dumps({"index": i}),
+            "jsonheavydemo_tags": json_dumps([f"tag-{i}"]),
+            "jsonheavydemo_profile": json_dumps({"age": i % 100}),
+        }
+        for i in range(num_rows)
+    ]
+
+    # Baseline: one loader call per field and row. Dicts skip model construction, which
+    # would otherwise dwarf the parsing time being compared.
+    def cast_per_row() -> list[Any]:
+        return optimize_exec_casting(
+            values, select_raw, select_types, output_mode="dict"
+        )
+
+    def cast_batched() -> list[Any]:
+        return optimize_exec_casting(
+            values, select_raw, select_types, output_mode="dict", batch_json=True
+        )
+
+    assert cast_batched() == cast_per_row()
+
+    per_row_time = time_callable(cast_per_row, num_loops)
+    batched_time = time_callable(cast_batched, num_loops)
+
+    speedup = (per_row_time - batched_time) / per_row_time * 100
+    LOGGER.info(
+        f"Batched JSON parsing: {batched_time:.4f}s vs per-row parsing {per_row_time:.4f}s ({speedup:.2f}% faster)"
+    )
+    CONSOLE.print(
+        f"Batched JSON parsing: {batched_time:.4f}s vs per-row parsing {per_row_time:.4f}s ({speedup:.2f}% faster)"
+    )
+
+    assert (
+        batched_time <= per_row_time
+    ), f"Batched parsing took {batched_time:.4f}s, slower than per-row {per_row_time:.4f}s"
+
+
+@pytest.mark.integration_tests
+def test_benchmark_column_select_casting():
+    num_rows = 50_000
+    num_loops = 10
+
+    select_raw, select_types = build_selects(WideDemo.id, WideDemo.col_0)
+    values = [
+        {"widedemo_id": i, "widedemo_col_0": f"value {i}"} for i in range(num_rows)
+    ]
+
+    # Baseline: iteration materializes the same tuples through the general row loop
+    def cast_general() -> list[Any]:
+        return list(optimize_exec_iter(values, select_raw, select_types))
+
+    def cast_columns() -> list[Any]:
+        return optimize_exec_casting(values, select_raw, select_types)
+
+    assert cast_columns() == cast_general()
+
+    general_time = time_callable(cast_general, num_loops)
+    column_time = time_callable(cast_columns, num_loops)
+
+    speedup = (general_time - column_time) / general_time * 100
+    LOGGER.info(
+        f"Column select casting: {column_time:.4f}s vs general path {general_time:.4f}s ({speedup:.2f}% faster)"
+    )
+    CONSOLE.print(
+        f"Column select casting: {column_time:.4f}s vs general path {general_time:.4f}s ({speedup:.2f}% faster)"
+    )
+
+    assert (
+        column_time <= general_time
+    ), f"Column select path took {column_time:.4f}s, slower than the general {general_time:.4f}s"
+
+
+@pytest.mark.integration_tests
+def test_benchmark_kwargs_buffer_reuse():
+    num_rows = 10_000
+    num_loops = 10
+
+    select_raw, select_types = build_selects(WideDemo)
+    values = [build_wide_row(i) for i in range(num_rows)]
+
+    # Same select and row loop, with only the buffer switched off
+    fresh_time = time_callable(
+        lambda: optimize_exec_casting(
+            values, select_raw, select_types, reuse_buffers=False
+        ),
+        num_loops,
+    )
+    reuse_time = time_callable(
+        lambda: optimize_exec_casting(values, select_raw, select_types), num_loops
+    )
+
+    speedup = (fresh_time - reuse_time) / fresh_time * 100
+    LOGGER.info(
+        f"Reused kwargs buffer: {reuse_time:.4f}s vs fresh dicts {fresh_time:.4f}s ({speedup:.2f}% faster)"
+    )
+    CONSOLE.print(
+        f"Reused kwargs buffer: {reuse_time:.4f}s vs fresh dicts {fresh_time:.4f}s ({speedup:.2f}% faster)"
+    )
+
+    assert (
+        reuse_time <= fresh_time
+    ), f"Buffer reuse took {reuse_time:.4f}s, slower than fresh dicts at {fresh_time:.4f}s"
+
+
+@pytest.mark.integration_tests
+def test_benchmark_result_allocations():
+    num_rows = 100_000
+    allowed_overhead = 5
+
+    # Scalar selects hand back the row's own value, so the result list is the only allocation
+    # that scales with the number of rows
+    select_raw, select_types = build_selects(func.count(WideDemo.id))
+    values = [{"aggregate_0": i} for i in range(num_rows)]
+    optimize_exec_casting(values[:10], select_raw, select_types)
+
+    start_tracing()
+    result = optimize_exec_casting(values, select_raw, select_types)
+    _, peak_memory = get_traced_memory()
+    stop_tracing()
+
+    # Floor: a list sized up front, without any growth reallocations
+    expected_memory = getsizeof([None] * num_rows)
+    assert getsizeof(result) == expected_memory
+
+    memory_diff = (peak_memory - expected_memory) / expected_memory * 100
+    LOGGER.info(
+        f"Result allocations: {peak_memory} bytes vs preallocated list {expected_memory} bytes ({memory_diff:.2f}%)"
+    )
+    CONSOLE.print(
+        f"Result allocations: {peak_memory} bytes vs preallocated list {expected_memory} bytes ({memory_diff:.2f}%)"
+    )
+
+    assert (
+        memory_diff <= allowed_overhead
+    ), f"Allocation overhead {memory_diff:.2f}% exceeds the {allowed_overhead}% threshold"
+
+
+def wide_model_scenario(num_rows: int) -> Scenario:
+    values = [build_wide_row(i) for i in range(num_rows)]
+    kwargs_rows = [
+        {key.removeprefix("widedemo_"): value for key, value in row.items()}
+        for row in values
+    ]
+    return values, (WideDemo,), lambda: [WideDemo(**kwargs) for kwargs in kwargs_rows]
+
+
+def column_tuples_scenario(num_rows: int) -> Scenario:
+    values = [build_wide_row(i) for i in range(num_rows)]
+    keys = [f"widedemo_col_{column}" for column in range(WIDE_TABLE_COLUMNS)]
+    fields = tuple(
+        getattr(WideDemo, f"col_{column}") for column in range(WIDE_TABLE_COLUMNS)
+    )
+    return values, fields, lambda: [tuple(row[key] for key in keys) for row in values]
+
+
+def json_rows_scenario(num_rows: int) -> Scenario:
+    values = [build_json_row(i) for i in range(num_rows)]
+    return (
+        values,
+        (JsonHeavyDemo,),
+        lambda: [
+            JsonHeavyDemo(
+                id=row["jsonheavydemo_id"],
+                settings=json_loads(row["jsonheavydemo_settings"]),
+                tags=json_loads(row["jsonheavydemo_tags"]),
+                profile=json_loads(row["jsonheavydemo_profile"]),
+            )
+            for row in values
+        ],
+    )
+
+
+def large_result_scenario(num_rows: int) -> Scenario:
+    values = [
+        {
+            "compactdemo_id": i,
+            "compactdemo_name": f"Item {i}",
+            "compactdemo_score": i / 2,
+        }
+        for i in range(num_rows)
+    ]
+    kwargs_rows = [
+        {"id": i, "name": f"Item {i}", "score": i / 2} for i in range(num_rows)
+    ]
+    return (
+        values,
+        (CompactDemo,),
+        lambda: [CompactDemo(**kwargs) for kwargs in kwargs_rows],
+    )
+
+
+@pytest.mark.integration_tests
+@pytest.mark.parametrize(
+    "scenario, num_rows, allowed_overhead",
+    [
+        (wide_model_scenario, 10_000, 50),
+        (column_tuples_scenario, 20_000, 100),
+        (json_rows_scenario, 5_000, 50),
+        (large_result_scenario, 200_000, 50),
+    ],
+)
+def test_benchmark_materialization_paths(
+    scenario: Callable[[int], Scenario],
+    num_rows: int,
+    allowed_overhead: float,
+):
+    """
+    One entry per materialization path, so speeding up one path can't silently slow
+    down another. Each path is timed against a baseline that builds the same results
+    by hand, from values already in the shape its constructor expects.
+
+    """
+    num_loops = 5
+
+    values, fields, baseline = scenario(num_rows)
+    select_raw, select_types = build_selects(*fields)
+    name = scenario.__name__.removesuffix("_scenario")
+
+    # Warm up the plan cache and the constructors, so the first loop isn't an outlier
+    optimize_exec_casting(values[:10], select_raw, select_types)
+
+    baseline_time = time_callable(baseline, num_loops)
+    casting_time = time_callable(
+        lambda: optimize_exec_casting(values, select_raw, select_types), num_loops
+    )
+
+    performance_diff = (casting_time - baseline_time) / baseline_time * 100
+    rows_per_second = num_rows / casting_time
+    LOGGER.info(
+        f"{name} ({num_rows} rows): {casting_time:.4f}s ({rows_per_second:,.0f} rows/s) vs baseline {baseline_time:.4f}s ({performance_diff:.2f}%)"
+    )
+    CONSOLE.print(
+        f"{name} ({num_rows} rows): {casting_time:.4f}s ({rows_per_second:,.0f} rows/s) vs baseline {baseline_time:.4f}s ({performance_diff:.2f}%)"
+    )
+
+    assert (
+        performance_diff <= allowed_overhead
+    ), f"{name} overhead {performance_diff:.2f}% exceeds {allowed_overhead}%"
@@ -11,6 +11,7 @@ from iceaxe.__tests__.conf_models import (
 )
 from iceaxe.functions import func
 from iceaxe.queries import QueryBuilder, and_, or_, select
+from iceaxe.typing import is_function_metadata
 
 
 class UserStatus(StrEnum):
@@ -189,6 +190,18 @@ def test_function_count():
     )
 
 
+def test_function_output_alias():
+    total = func.abs(func.as_(func.count(UserDemo.id), "total"))
+
+    # Only changes where rows are read from, not the SQL that's generated
+    assert is_function_metadata(total)
+    assert total.output_alias == "total"
+    assert QueryBuilder().select(total).build() == (
+        'SELECT abs(count("userdemo"."id")) AS aggregate_0 FROM "userdemo"',
+        [],
+    )
+
+
 def test_function_distinct():
     new_query = QueryBuilder().select(func.distinct(UserDemo.name))
     assert new_query.build() == (
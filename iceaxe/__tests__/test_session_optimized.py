@@ -0,0 +1,4971 @@
+from _thread import interrupt_main
+from collections import deque
+from collections.abc import Iterator, Mapping
+from dataclasses import dataclass
+from datetime import date, datetime, time, timedelta, timezone
+from decimal import Decimal
+from enum import IntEnum, StrEnum
+from itertools import product
+from ipaddress import IPv4Address, IPv6Address, ip_address
+from json import dumps as json_dumps, loads as json_loads
+from math import inf, isfinite, isnan
+from pathlib import Path
+from signal import SIG_IGN, SIGINT, SIGUSR1, signal
+from types import SimpleNamespace
+from typing import Annotated, Any, ClassVar, NotRequired, TypedDict
+from uuid import UUID
+from warnings import catch_warnings, simplefilter
+
+import pytest
+from pydantic import BaseModel, ValidationError, field_validator
+
+from iceaxe.__tests__.conf_models import JsonDemo, UserDemo
+from iceaxe.alias_values import alias_table
+from iceaxe.base import TableBase
+from iceaxe.field import Field
+from iceaxe.functions import func
+from iceaxe.queries import QueryBuilder
+from iceaxe.session_optimized import (
+    FrozenModelError,
+    LazyModel,
+    MissingColumnWarning,
+    SKIP_MISSING,
+    MultipleRowsError,
+    NonFiniteJSONError,
+    NoRowsError,
+    PreparedSelect,
+    clear_plan_cache,
+    optimize_exec_auto,
+    optimize_exec_casting,
+    optimize_exec_chunked,
+    optimize_exec_columnar,
+    optimize_exec_count,
+    optimize_exec_explain,
+    optimize_exec_first,
+    optimize_exec_grouped,
+    optimize_exec_into,
+    optimize_exec_iter,
+    optimize_exec_json,
+    optimize_exec_lenient,
+    optimize_exec_limited,
+    optimize_exec_map,
+    optimize_exec_one,
+    optimize_exec_scalar,
+    optimize_exec_single,
+    optimize_exec_typed,
+    plan_cache_info,
+)
+from iceaxe.typing import is_base_table, is_column, is_function_metadata
+
+
+class EmployeeDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    manager_id: int | None = None
+
+
+class AssetDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    location: Path
+    thumbnail: Path | None = None
+
+
+class LedgerDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    tokens: list[UUID] = Field(is_json=True)
+    amounts: list[list[Decimal | None]] | None = Field(is_json=True, default=None)
+    owner: UUID | None = Field(is_json=True, default=None)
+
+
+class GrantDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    tokens: list[UUID] = Field(is_json=True, accept_scalar=True)
+
+    @field_validator("tokens", mode="before")
+    @classmethod
+    def wrap_single_token(cls, value: Any) -> Any:
+        return value if isinstance(value, list) else [value]
+
+
+class BinaryDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    payload: bytes
+    document: dict[str, Any] = Field(is_json=True)
+
+
+class ValidatedDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+
+    validated_names: ClassVar[list[str]] = []
+
+    @field_validator("name")
+    @classmethod
+    def record_name(cls, value: str) -> str:
+        cls.validated_names.append(value)
+        return value
+
+
+class PointDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    x: int
+    y: int = 0
+
+    # Positional-only, like a constructor ported from a plain class
+    def __init__(self, id: int, x: int, y: int, /):
+        super().__init__(id=id, x=x, y=y)
+
+
+class KeywordDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+
+    init_args: ClassVar[list[tuple[Any, ...]]] = []
+
+    def __init__(self, *args: Any, **data: Any):
+        KeywordDemo.init_args.append(args)
+        super().__init__(**data)
+
+
+class ProfileRow(TypedDict):
+    id: int
+    name: str
+    settings: Annotated[dict[str, Any], Field(is_json=True)]
+    nickname: NotRequired[str | None]
+
+
+class Status(StrEnum):
+    ACTIVE = "active"
+    ARCHIVED = "archived"
+
+
+class Priority(IntEnum):
+    LOW = 1
+    HIGH = 2
+
+
+class FlagDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    active: bool
+    archived: bool | None = None
+
+
+class TicketDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    status: Status
+    priority: Priority | None = None
+
+
+class ContactDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    email: str | None = "unknown@example.com"
+
+
+class AuditDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    created_at: datetime = Field(default_factory=datetime.now)
+    notes: list[str] = Field(default_factory=list)
+    status: str = "active"
+
+
+class EventDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    created_at: datetime
+    updated_at: datetime | None = None
+
+
+class InvoiceDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    total: Decimal = Field(is_money=True)
+    refund: Decimal | None = Field(is_money=True, default=None)
+
+
+class BitDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    flags: str = Field(is_bit=True)
+    mask: int | None = Field(is_bit=True, default=None)
+    packed: bytes | None = Field(is_bit=True, default=None)
+
+
+class EpochDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    created_at: datetime = Field(epoch_unit="seconds")
+    logged_at: datetime | None = Field(epoch_unit="milliseconds", default=None)
+    checkpoints: list[datetime] = Field(epoch_unit="seconds", default_factory=list)
+
+
+class ScheduleDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    day: date
+    starts_at: time | None = None
+    duration: timedelta | None = None
+
+
+class TokenDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    token: UUID
+    parent_token: UUID | None = None
+
+
+class MeasureDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    reading: float
+    ratio: float | None = None
+
+
+class PriceDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    amount: Decimal
+    discount: Decimal | None = None
+
+
+class Metadata(BaseModel):
+    source: str
+    version: int = 1
+
+
+class DocumentDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    metadata: Metadata | None = Field(is_json=True, default=None)
+    revisions: list[Metadata] = Field(is_json=True, default_factory=list)
+
+
+class EnvelopeDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    user_id: UUID | None = Field(is_json=True, json_path="user.id", default=None)
+    origin: Metadata | None = Field(is_json=True, json_path="meta.origin", default=None)
+
+
+class EncodedDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    payload: dict[str, Any] = Field(is_json=True, double_decode=True)
+    note: str | None = Field(is_json=True, double_decode=True, default=None)
+
+
+class PipelineDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    origin: Metadata | None = Field(
+        is_json=True,
+        json_path="meta.origin",
+        coercions=["decode", "path", "submodel"],
+        default=None,
+    )
+    # The payload wraps the metadata as JSON text of its own
+    wrapped: Metadata | None = Field(
+        is_json=True,
+        json_path="data",
+        coercions=["decode", "path", "decode", "submodel"],
+        default=None,
+    )
+
+
+class PreferencesDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    settings: dict[str, Any] = Field(is_json=True, default_json={})
+    tags: list[str] = Field(is_json=True, default_json=[])
+
+
+class PackedDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    attributes: dict[str, Any] = Field(is_msgpack=True)
+    snapshot: Metadata | None = Field(is_msgpack=True, default=None)
+
+
+class PostDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    user_id: int
+
+
+class AuthorDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    name: str
+    posts: list[PostDemo] = Field(default_factory=list, exclude=True)
+
+
+class TenantDemo(TableBase):
+    tenant_id: int = Field(primary_key=True)
+    id: int = Field(primary_key=True)
+    name: str
+    members: list[PostDemo] = Field(default_factory=list, exclude=True)
+
+
+class AliasDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    user_id: int = Field(validation_alias="uid")
+    display: str = Field(alias="display_name")
+
+
+class ArrayDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    scores: list[int]
+    labels: list[str] | None = None
+    grid: list[list[int]] | None = None
+    amounts: list[Decimal | None] | None = None
+
+
+class HostDemo(TableBase):
+    id: int = Field(primary_key=True, default=None)
+    address: IPv4Address | IPv6Address | None = Field(codec="inet", default=None)
+
+
+class RankedDemo(TableBase):
+    model_config = {"extra": "allow"}
+
+    id: int = Field(primary_key=True, default=None)
+    name: str
+
+
+@dataclass
+class UserRecord:
+    id: int
+    name: str
+    email: str
+
+
+def build_selects(*fields: Any):
+    """
+    Mirror the select metadata that DBConnection.exec passes to the optimized casting.
+
+    """
+    query = QueryBuilder().select(fields)
+    select_types = [
+        (
+            is_base_table(select_raw),
+            is_column(select_raw),
+            is_function_metadata(select_raw),
+        )
+        for select_raw in query._select_raw
+    ]
+    return query._select_raw, select_types
+
+
+#
+# Missing keys
+#
+
+
+def test_missing_field_strict_raises_with_row():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+        },
+        {"userdemo_id": 2, "userdemo_name": "Jane"},
+    ]
+
+    with pytest.raises(KeyError, match="userdemo_email.*row 1") as exc_info:
+        optimize_exec_casting(values, select_raw, select_types)
+
+    assert isinstance(exc_info.value.__cause__, KeyError)
+
+
+def test_missing_field_lenient_uses_model_default():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [{"userdemo_name": "John", "userdemo_email": "john@example.com"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=False)
+
+    assert result == [UserDemo(name="John", email="john@example.com")]
+
+
+def test_missing_column_strict_raises_with_row():
+    select_raw, select_types = build_selects(
+        UserDemo.id, UserDemo.name, UserDemo.email
+    )
+    values = [{"userdemo_id": 1, "userdemo_email": "john@example.com"}]
+
+    with pytest.raises(KeyError, match="userdemo_name.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_missing_column_lenient_preserves_alignment():
+    select_raw, select_types = build_selects(
+        UserDemo.id, UserDemo.name, UserDemo.email
+    )
+    values = [{"userdemo_id": 1, "userdemo_email": "john@example.com"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=False)
+
+    assert result == [(1, None, "john@example.com")]
+
+
+def test_missing_function_metadata_lenient():
+    select_raw, select_types = build_selects(
+        UserDemo.id, func.count(UserDemo.id), UserDemo.email
+    )
+    values = [{"userdemo_id": 1, "userdemo_email": "john@example.com"}]
+
+    with pytest.raises(KeyError, match="aggregate_0.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=False)
+    assert result == [(1, None, "john@example.com")]
+
+
+@pytest.mark.parametrize(
+    "cast",
+    [
+        optimize_exec_casting,
+        lambda *args, **kwargs: list(optimize_exec_iter(*args, **kwargs)),
+        lambda *args, **kwargs: optimize_exec_chunked(*args, chunk_size=1, **kwargs),
+    ],
+)
+def test_missing_select_keeps_tuple_arity(cast: Any):
+    select_raw, select_types = build_selects(
+        UserDemo.id, func.count(UserDemo.id), UserDemo.email
+    )
+    values = [
+        {"userdemo_id": 1, "userdemo_email": "john@example.com"},
+        {"aggregate_0": 2},
+    ]
+
+    result = cast(values, select_raw, select_types, strict=False)
+
+    # Every row unpacks into one value per select, whichever keys it's missing
+    (a, b, c) = result[0]
+    assert (a, b, c) == (1, None, "john@example.com")
+    (a, b, c) = result[1]
+    assert (a, b, c) == (None, 2, None)
+
+
+@pytest.mark.parametrize("strict", [True, False])
+def test_present_null_is_not_missing(strict: bool):
+    select_raw, select_types = build_selects(ContactDemo)
+    values = [
+        {"contactdemo_id": 1, "contactdemo_name": "John", "contactdemo_email": None}
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=strict)
+
+    assert result == [ContactDemo(id=1, name="John", email=None)]
+
+
+def test_absent_key_uses_default_or_raises():
+    select_raw, select_types = build_selects(ContactDemo)
+    values = [{"contactdemo_id": 1, "contactdemo_name": "John"}]
+
+    with pytest.raises(KeyError, match="contactdemo_email.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=False)
+    assert result == [ContactDemo(id=1, name="John", email="unknown@example.com")]
+
+
+@pytest.mark.parametrize(
+    "options",
+    [{}, {"validate": False}, {"output_mode": "dict"}],
+)
+def test_missing_field_lenient_applies_default_factory(options: dict[str, Any]):
+    select_raw, select_types = build_selects(AuditDemo)
+    values = [
+        {"auditdemo_id": 1, "auditdemo_name": "John"},
+        {"auditdemo_id": 2, "auditdemo_name": "Jane"},
+    ]
+    before = datetime.now()
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, strict=False, **options
+    )
+
+    rows = [row if isinstance(row, dict) else row.model_dump() for row in result]
+    assert all(before <= row["created_at"] <= datetime.now() for row in rows)
+    assert [row["status"] for row in rows] == ["active", "active"]
+    assert rows[0]["notes"] == [] and rows[0]["notes"] is not rows[1]["notes"]
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_on_missing_computes_placeholders(validate: bool):
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.email)
+    values = [
+        {"contactdemo_id": 1, "contactdemo_name": "John"},
+        {"contactdemo_id": 2, "contactdemo_email": "jane@example.com"},
+    ]
+    calls: list[tuple[str, int]] = []
+
+    def placeholder(field_name: str, row_index: int) -> Any:
+        calls.append((field_name, row_index))
+        return f"<{field_name} {row_index}>"
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        strict=False,
+        validate=validate,
+        on_missing=placeholder,
+    )
+
+    assert result[0] == (
+        ContactDemo(id=1, name="John", email="<email 0>"),
+        "<email 0>",
+    )
+    assert result[1] == (
+        ContactDemo(id=2, name="<name 1>", email="jane@example.com"),
+        "<email 1>",
+    )
+    assert calls == [("email", 0), ("email", 0), ("name", 1), ("email", 1)]
+
+
+def test_on_missing_skip_uses_defaults():
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.email)
+    values = [{"contactdemo_id": 1, "contactdemo_name": "John"}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        strict=False,
+        on_missing=lambda field_name, row_index: SKIP_MISSING,
+    )
+
+    assert result == [
+        (ContactDemo(id=1, name="John", email="unknown@example.com"), None)
+    ]
+
+
+def test_warn_on_missing_once_per_column():
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.email)
+    values = [
+        {"contactdemo_id": 1, "contactdemo_name": "John"},
+        {"contactdemo_id": 2, "contactdemo_name": "Jane"},
+        {"contactdemo_id": 3, "contactdemo_email": "jim@example.com"},
+    ]
+
+    # Dicts skip validation, so the row without a name still builds
+    with catch_warnings(record=True) as caught:
+        simplefilter("always")
+        result = optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            strict=False,
+            output_mode="dict",
+            warn_on_missing=True,
+        )
+
+    assert result[1] == {
+        "contactdemo": {"id": 2, "name": "Jane", "email": "unknown@example.com"},
+        "email": None,
+    }
+    assert all(warning.category is MissingColumnWarning for warning in caught)
+    assert [str(warning.message) for warning in caught] == [
+        "Key 'contactdemo_email' for 'email' not found in row 0 (select index 0).",
+        "Key 'userdemo_email' for 'email' not found in row 0 (select index 1).",
+        "Key 'contactdemo_name' for 'name' not found in row 2 (select index 0).",
+    ]
+
+
+def test_warn_on_missing_is_off_by_default():
+    select_raw, select_types = build_selects(ContactDemo)
+    values = [{"contactdemo_id": 1, "contactdemo_name": "John"}]
+
+    with catch_warnings(record=True) as caught:
+        simplefilter("always")
+        optimize_exec_casting(values, select_raw, select_types, strict=False)
+
+    assert caught == []
+
+
+#
+# Error positions
+#
+
+
+def test_missing_column_reports_select_index():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name, UserDemo.email)
+    values = [
+        {"userdemo_id": i, "userdemo_name": f"User {i}", "userdemo_email": "a@b.com"}
+        for i in range(4)
+    ]
+    values.append({"userdemo_id": 4, "userdemo_name": "User 4"})
+
+    with pytest.raises(
+        KeyError, match=r"'userdemo_email' not found in row 4 \(select index 2\)"
+    ):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "fields",
+    [
+        (UserDemo,),
+        (UserDemo.id, UserDemo.name),
+        (UserDemo.id,),
+    ],
+)
+@pytest.mark.parametrize("malformed_row", [None, 7])
+def test_malformed_row_reports_row(fields: tuple[Any, ...], malformed_row: Any):
+    select_raw, select_types = build_selects(*fields)
+    row = {
+        "userdemo_id": 1,
+        "userdemo_name": "John",
+        "userdemo_email": "john@example.com",
+    }
+    values = [row, malformed_row, row]
+
+    with pytest.raises(
+        TypeError,
+        match=rf"Row 1 is not a mapping \(got {type(malformed_row).__name__}\)",
+    ):
+        optimize_exec_casting(values, select_raw, select_types, batch_json=True)
+
+
+def test_malformed_row_for_row_format():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+
+    with pytest.raises(TypeError, match=r"Row 1 is not a sequence \(got int\)"):
+        optimize_exec_casting(
+            [(1, "John"), 2], select_raw, select_types, row_format="tuple"
+        )
+    with pytest.raises(TypeError, match=r"Row 0 is None, expected a row object"):
+        optimize_exec_casting([None], select_raw, select_types, row_format="attr")
+
+
+def test_missing_table_field_reports_select_index():
+    select_raw, select_types = build_selects(func.count(UserDemo.id), UserDemo)
+    values = [{"aggregate_0": 1, "userdemo_id": 1, "userdemo_name": "John"}]
+
+    with pytest.raises(
+        KeyError, match=r"field 'email' not found in row 0 \(select index 1\)"
+    ):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_invalid_value_reports_select_index():
+    select_raw, select_types = build_selects(EventDemo.id, EventDemo)
+    values = [
+        {
+            "eventdemo_id": 1,
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "eventdemo_updated_at": None,
+        },
+        {
+            "eventdemo_id": 2,
+            "eventdemo_created_at": "yesterday",
+            "eventdemo_updated_at": None,
+        },
+    ]
+
+    with pytest.raises(
+        ValueError, match=r"'created_at' in row 1 \(select index 1\): 'yesterday'"
+    ):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_construction_error_notes_position():
+    select_raw, select_types = build_selects(UserDemo.id, ValidatedDemo)
+    values = [
+        {"userdemo_id": 1, "validateddemo_id": 1, "validateddemo_name": "John"},
+        {"userdemo_id": 2, "validateddemo_id": 2, "validateddemo_name": 2},
+    ]
+
+    with pytest.raises(ValidationError) as exc_info:
+        optimize_exec_casting(values, select_raw, select_types)
+
+    assert exc_info.value.__notes__ == ["Raised while casting row 1 (select index 1)"]
+
+
+def test_transform_error_notes_position():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [{"userdemo_id": 1, "userdemo_name": None}]
+
+    with pytest.raises(AttributeError) as exc_info:
+        optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            transforms={"userdemo_name": lambda name: name.upper()},
+        )
+
+    assert exc_info.value.__notes__ == ["Raised while casting row 0 (select index 1)"]
+
+
+#
+# Derived select types
+#
+
+
+def test_auto_derives_select_types():
+    select_raw, select_types = build_selects(
+        UserDemo, EventDemo.created_at, func.count(UserDemo.id)
+    )
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "aggregate_0": 3,
+        }
+    ]
+
+    result = optimize_exec_auto(values, select_raw)
+
+    assert result == optimize_exec_casting(values, select_raw, select_types)
+    assert result == [
+        (
+            UserDemo(id=1, name="John", email="john@example.com"),
+            datetime(2024, 1, 1),
+            3,
+        )
+    ]
+
+
+def test_auto_accepts_options():
+    select_raw, _ = build_selects(UserDemo.id, UserDemo.name)
+    values = [{"userdemo_id": 1}]
+
+    assert optimize_exec_auto(values, select_raw, strict=False) == [(1, None)]
+
+
+@pytest.mark.parametrize("drop_from", ["select_raw", "select_types"])
+def test_mismatched_select_lengths(drop_from: str):
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    if drop_from == "select_raw":
+        select_raw = select_raw[:1]
+    else:
+        select_types = select_types[:1]
+    values = [{"userdemo_id": 1, "userdemo_name": "John"}]
+
+    expected_lengths = (
+        "1 select_raws but 2 select_types"
+        if drop_from == "select_raw"
+        else "2 select_raws but 1 select_types"
+    )
+    with pytest.raises(ValueError, match=expected_lengths):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "table, received",
+    [
+        ({"id": 1}, "dict"),
+        (UserDemo(id=1, name="John", email="j"), "UserDemo"),
+        # Has pydantic fields, but no table name or client fields to plan with
+        (Metadata, "class Metadata"),
+    ],
+)
+def test_table_select_must_be_model_class(table: Any, received: str):
+    select_raw, select_types = build_selects(UserDemo.id)
+    select_raw = [*select_raw, table]
+    select_types = [*select_types, (True, False, False)]
+
+    with pytest.raises(
+        TypeError, match=rf"Select index 1 .* got {received} instead of a model class"
+    ):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+
+#
+# Plan construction
+#
+
+
+class RecordingFieldInfo:
+    """
+    Stand-in for a pydantic `FieldInfo` that records every attribute read off it.
+
+    """
+
+    reads: ClassVar[list[str]] = []
+
+    def __init__(self, annotation: Any, **attributes: Any):
+        self.__dict__.update(
+            annotation=annotation,
+            default=None,
+            default_factory=None,
+            alias=None,
+            validation_alias=None,
+            exclude=None,
+            is_json=False,
+            is_msgpack=False,
+            json_path=None,
+            double_decode=False,
+            accept_scalar=False,
+            coercions=None,
+            default_json=None,
+            codec=None,
+            epoch_unit=None,
+            is_money=False,
+            is_bit=False,
+        )
+        self.__dict__.update(attributes)
+
+    def __getattribute__(self, name: str) -> Any:
+        if not name.startswith("__"):
+            RecordingFieldInfo.reads.append(name)
+        return object.__getattribute__(self, name)
+
+
+FAKE_TABLE_FIELDS = {
+    "id": RecordingFieldInfo(int),
+    "secret": RecordingFieldInfo(str, exclude=True),
+    "settings": RecordingFieldInfo(dict[str, Any], is_json=True),
+}
+
+
+class FakeTable(TableBase):
+    @classmethod
+    def get_client_fields(cls) -> dict[str, Any]:
+        return FAKE_TABLE_FIELDS
+
+
+def test_field_info_only_read_while_planning():
+    def cast_rows(num_rows: int) -> tuple[list[Any], list[str]]:
+        # Plans are otherwise reused from the first call, so later calls read nothing
+        clear_plan_cache()
+        RecordingFieldInfo.reads.clear()
+        values = [
+            {"faketable_id": i, "faketable_secret": "x", "faketable_settings": "{}"}
+            for i in range(num_rows)
+        ]
+        result = optimize_exec_casting(
+            values, [FakeTable], [(True, False, False)], output_mode="dict"
+        )
+        return result, list(RecordingFieldInfo.reads)
+
+    single_result, single_reads = cast_rows(1)
+    many_result, many_reads = cast_rows(100)
+
+    assert single_result == [{"id": 0, "settings": {}}]
+    assert many_result[99] == {"id": 99, "settings": {}}
+    # Every read happens while planning, so the row count doesn't change them
+    assert many_reads == single_reads
+    assert single_reads.count("exclude") == 3
+    assert "is_json" in single_reads
+
+
+def test_plan_cache_reused_across_calls():
+    clear_plan_cache()
+    select_raw, select_types = [FakeTable], [(True, False, False)]
+    values = [{"faketable_id": 1, "faketable_secret": "x", "faketable_settings": "{}"}]
+
+    first = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+    RecordingFieldInfo.reads.clear()
+    second = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        output_mode="dict",
+        transforms={"faketable_id": lambda value: value + 1},
+    )
+    third = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+
+    assert first == third == [{"id": 1, "settings": {}}]
+    # Per-call settings apply to copies, so they don't leak into later calls
+    assert second == [{"id": 2, "settings": {}}]
+    assert RecordingFieldInfo.reads == []
+    assert plan_cache_info() == {"hits": 2, "misses": 1, "size": 1, "max_size": 256}
+
+
+def test_plan_cache_keyed_by_prefix():
+    clear_plan_cache()
+    select_raw, select_types = build_selects(UserDemo)
+    values = [{"users_id": 1, "users_name": "John", "users_email": "j@example.com"}]
+
+    with pytest.raises(KeyError, match="userdemo_id"):
+        optimize_exec_casting(values, select_raw, select_types)
+    result = optimize_exec_casting(
+        values, select_raw, select_types, key_prefixes={UserDemo: "users_"}
+    )
+
+    assert result == [UserDemo(id=1, name="John", email="j@example.com")]
+    assert plan_cache_info()["misses"] == 2
+
+
+def legacy_field(
+    name: str,
+    annotation: Any,
+    *,
+    alias: str | None = None,
+    default: Any = None,
+    required: bool = True,
+    exclude: Any = None,
+    **extra: Any,
+) -> SimpleNamespace:
+    """
+    Mirror the pydantic v1 `ModelField` attributes that casting reads.
+
+    """
+    return SimpleNamespace(
+        name=name,
+        alias=name if alias is None else alias,
+        outer_type_=annotation,
+        required=required,
+        default=default,
+        default_factory=None,
+        field_info=SimpleNamespace(exclude=exclude, extra=extra),
+    )
+
+
+class LegacyDemo:
+    """
+    Minimal pydantic v1 style model, with `__fields__` and `construct` in place of the
+    v2 `model_fields` and `model_construct`.
+
+    """
+
+    __config__ = SimpleNamespace(extra="ignore")
+    __fields__ = {
+        "id": legacy_field("id", int, primary_key=True),
+        "full_name": legacy_field("full_name", str, alias="fullName"),
+        "token": legacy_field("token", str, exclude=...),
+        "settings": legacy_field(
+            "settings", dict, required=False, default=None, is_json=True
+        ),
+    }
+
+    def __init__(self, **data: Any):
+        self.data = data
+        self.validated = True
+
+    @classmethod
+    def construct(cls, **values: Any) -> "LegacyDemo":
+        instance = cls.__new__(cls)
+        instance.data = values
+        instance.validated = False
+        return instance
+
+    @classmethod
+    def get_table_name(cls) -> str:
+        return "legacydemo"
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_pydantic_v1_model(validate: bool):
+    values = [
+        {
+            "legacydemo_id": 1,
+            "legacydemo_fullName": "John",
+            "legacydemo_settings": '{"theme": "dark"}',
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values, [LegacyDemo], [(True, False, False)], validate=validate
+    )
+
+    assert len(result) == 1
+    assert result[0].validated == validate
+    # The validating constructor takes aliases, construct takes field names
+    name_key = "fullName" if validate else "full_name"
+    assert result[0].data == {
+        "id": 1,
+        name_key: "John",
+        "settings": {"theme": "dark"},
+    }
+
+
+def test_pydantic_v1_model_lenient_defaults():
+    values = [{"legacydemo_id": 1, "legacydemo_fullName": "John"}]
+
+    result = optimize_exec_casting(
+        values,
+        [LegacyDemo],
+        [(True, False, False)],
+        strict=False,
+        output_mode="dict",
+    )
+
+    assert result == [{"id": 1, "full_name": "John", "settings": None}]
+
+#
+# Scalar casting
+#
+
+
+def test_scalar_function():
+    select_raw, select_types = build_selects(func.count(UserDemo.id))
+    values = [{"aggregate_0": 3}, {"aggregate_0": 5}]
+
+    result = optimize_exec_scalar(values, select_raw[0], select_types[0])
+
+    assert result == [3, 5]
+
+
+def test_scalar_column_coerces():
+    select_raw, select_types = build_selects(EventDemo.created_at)
+    values = [{"eventdemo_created_at": "2024-01-01T00:00:00"}]
+
+    result = optimize_exec_scalar(values, select_raw[0], select_types[0])
+
+    assert result == [datetime(2024, 1, 1)]
+
+
+def test_scalar_rejects_table():
+    select_raw, select_types = build_selects(UserDemo)
+
+    with pytest.raises(ValueError, match="not table userdemo"):
+        optimize_exec_scalar([], select_raw[0], select_types[0])
+
+#
+# Single row casting
+#
+
+
+def test_exec_single_table():
+    select_raw, select_types = build_selects(UserDemo)
+    value = {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@e.com"}
+
+    assert optimize_exec_single(value, select_raw, select_types) == UserDemo(
+        id=1, name="John", email="j@e.com"
+    )
+
+
+def test_exec_single_matches_exec():
+    select_raw, select_types = build_selects(UserDemo, UserDemo.name)
+    value = {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@e.com"}
+
+    assert optimize_exec_single(value, select_raw, select_types) == (
+        optimize_exec_casting([value], select_raw, select_types)[0]
+    )
+
+
+def test_function_output_alias():
+    select_raw, select_types = build_selects(
+        UserDemo.name, func.as_(func.count(UserDemo.id), "total")
+    )
+    values = [
+        {"userdemo_name": "John", "total": 3},
+        # Rows keyed by the generated local name still resolve
+        {"userdemo_name": "Jane", "aggregate_0": 5},
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [("John", 3), ("Jane", 5)]
+
+
+def test_one_returns_single_row():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [{"userdemo_id": 1, "userdemo_name": "John"}]
+
+    assert optimize_exec_one(values, select_raw, select_types) == (1, "John")
+
+
+def test_one_without_rows():
+    select_raw, select_types = build_selects(UserDemo)
+
+    with pytest.raises(NoRowsError):
+        optimize_exec_one([], select_raw, select_types)
+
+
+def test_one_with_multiple_rows():
+    select_raw, select_types = build_selects(UserDemo.id)
+    pulled: list[int] = []
+
+    def rows():
+        for i in range(5):
+            pulled.append(i)
+            yield {"userdemo_id": i}
+
+    with pytest.raises(MultipleRowsError):
+        optimize_exec_one(rows(), select_raw, select_types)
+
+    # The rest of the cursor is never drained
+    assert pulled == [0, 1]
+
+
+def test_first_returns_first_row():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    pulled: list[int] = []
+
+    def rows():
+        for i in range(5):
+            pulled.append(i)
+            yield {"userdemo_id": i, "userdemo_name": f"User {i}"}
+
+    assert optimize_exec_first(rows(), select_raw, select_types) == (0, "User 0")
+    assert pulled == [0]
+
+
+def test_first_without_rows():
+    select_raw, select_types = build_selects(UserDemo)
+
+    assert optimize_exec_first([], select_raw, select_types) is None
+    assert optimize_exec_first(iter([]), select_raw, select_types) is None
+
+
+#
+# JSON fields
+#
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_json_typed_elements(validate: bool):
+    select_raw, select_types = build_selects(LedgerDemo)
+    first = UUID("12345678-1234-5678-1234-567812345678")
+    second = UUID("87654321-4321-8765-4321-876543218765")
+    values = [
+        {
+            "ledgerdemo_id": 1,
+            "ledgerdemo_tokens": f'["{first}", "{second}"]',
+            "ledgerdemo_amounts": "[[1.1, null], [2]]",
+            "ledgerdemo_owner": f'"{first}"',
+        },
+        {
+            "ledgerdemo_id": 2,
+            "ledgerdemo_tokens": "[]",
+            "ledgerdemo_amounts": None,
+            "ledgerdemo_owner": "null",
+        },
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].tokens == [first, second]
+    assert all(isinstance(token, UUID) for token in result[0].tokens)
+    assert result[0].amounts == [[Decimal("1.1"), None], [Decimal(2)]]
+    assert result[0].owner == first
+    assert result[1].tokens == []
+    assert result[1].amounts is None
+    assert result[1].owner is None
+
+
+def test_json_typed_elements_reject_scalars():
+    select_raw, select_types = build_selects(LedgerDemo)
+    values = [
+        {
+            "ledgerdemo_id": 1,
+            "ledgerdemo_tokens": '"12345678-1234-5678-1234-567812345678"',
+            "ledgerdemo_amounts": None,
+            "ledgerdemo_owner": None,
+        }
+    ]
+
+    with pytest.raises(ValueError, match=r"'tokens' in row 0 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_json_typed_elements_accept_scalars(validate: bool):
+    select_raw, select_types = build_selects(GrantDemo)
+    first = UUID("12345678-1234-5678-1234-567812345678")
+    second = UUID("87654321-4321-8765-4321-876543218765")
+    values = [
+        {"grantdemo_id": 1, "grantdemo_tokens": f'"{first}"'},
+        {"grantdemo_id": 2, "grantdemo_tokens": f'["{first}", "{second}"]'},
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    # The single value is converted on its own, and only the model's validator wraps it
+    assert result[0].tokens == ([first] if validate else first)
+    assert result[1].tokens == [first, second]
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_json_path_extracts_nested_values(validate: bool):
+    select_raw, select_types = build_selects(EnvelopeDemo)
+    user_id = UUID("12345678-1234-5678-1234-567812345678")
+    values = [
+        {
+            "envelopedemo_id": 1,
+            "envelopedemo_user_id": json_dumps(
+                {"user": {"id": str(user_id), "name": "John"}, "events": [1, 2, 3]}
+            ),
+            "envelopedemo_origin": json_dumps(
+                {"meta": {"origin": {"source": "import", "version": 2}}}
+            ),
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].user_id == user_id
+    assert result[0].origin == Metadata(source="import", version=2)
+
+
+@pytest.mark.parametrize(
+    "payload",
+    [
+        None,
+        "null",
+        "{}",
+        '{"user": null}',
+        '{"user": {"name": "John"}}',
+        '{"user": ["12345678-1234-5678-1234-567812345678"]}',
+    ],
+)
+def test_json_path_absent_is_none(payload: str | None):
+    select_raw, select_types = build_selects(EnvelopeDemo)
+    values = [
+        {
+            "envelopedemo_id": 1,
+            "envelopedemo_user_id": payload,
+            "envelopedemo_origin": '{"meta": {}}',
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result[0].user_id is None
+    assert result[0].origin is None
+
+
+@pytest.mark.parametrize("batch_json", [True, False])
+def test_double_decode_json(batch_json: bool):
+    select_raw, select_types = build_selects(EncodedDemo)
+    values = [
+        # A JSON string holding the escaped JSON object, like "\"{\\\"a\\\":1}\""
+        {
+            "encodeddemo_id": 1,
+            "encodeddemo_payload": json_dumps(json_dumps({"a": 1})),
+            "encodeddemo_note": None,
+        },
+        # Payloads that were only encoded once still parse as usual
+        {
+            "encodeddemo_id": 2,
+            "encodeddemo_payload": json_dumps({"a": 2}),
+            "encodeddemo_note": None,
+        },
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, batch_json=batch_json
+    )
+
+    assert result == [
+        EncodedDemo(id=1, payload={"a": 1}),
+        EncodedDemo(id=2, payload={"a": 2}),
+    ]
+
+
+@pytest.mark.parametrize(
+    "note, expected",
+    [
+        (json_dumps("plain text"), "plain text"),
+        (json_dumps("[not json"), "[not json"),
+        # Decoding stops after the second pass, even if the result is still JSON text
+        (json_dumps(json_dumps(json_dumps([1]))), json_dumps("[1]")),
+    ],
+)
+def test_double_decode_leaves_strings(note: str, expected: str):
+    select_raw, select_types = build_selects(EncodedDemo)
+    values = [
+        {"encodeddemo_id": 1, "encodeddemo_payload": "{}", "encodeddemo_note": note}
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result[0].note == expected
+
+
+@pytest.mark.parametrize("batch_json", [True, False])
+def test_coercion_steps_run_in_order(batch_json: bool):
+    select_raw, select_types = build_selects(PipelineDemo)
+    values = [
+        {
+            "pipelinedemo_id": 1,
+            "pipelinedemo_origin": json_dumps(
+                {"meta": {"origin": {"source": "import", "version": 2}}}
+            ),
+            "pipelinedemo_wrapped": json_dumps(
+                {"data": json_dumps({"source": "sync", "version": 3})}
+            ),
+        },
+        # Steps stop at a missing path, instead of decoding or building from None
+        {
+            "pipelinedemo_id": 2,
+            "pipelinedemo_origin": "{}",
+            "pipelinedemo_wrapped": "{}",
+        },
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, batch_json=batch_json
+    )
+
+    assert result == [
+        PipelineDemo(
+            id=1,
+            origin=Metadata(source="import", version=2),
+            wrapped=Metadata(source="sync", version=3),
+        ),
+        PipelineDemo(id=2, origin=None, wrapped=None),
+    ]
+
+
+@pytest.mark.parametrize(
+    "field_kwargs, message",
+    [
+        ({"is_json": True, "coercions": ["parse"]}, "Unknown coercion 'parse'"),
+        ({"is_json": True, "coercions": ["decode", "path"]}, "requires a json_path"),
+        ({"coercions": ["decode"]}, "only apply to JSON fields"),
+    ],
+)
+def test_coercion_steps_are_validated(field_kwargs: dict[str, Any], message: str):
+    class BadPipelineDemo(TableBase):
+        id: int = Field(primary_key=True, default=None)
+        payload: dict[str, Any] = Field(**field_kwargs)
+
+    select_raw, select_types = build_selects(BadPipelineDemo)
+
+    with pytest.raises(ValueError, match=message):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_default_json_for_missing_column(validate: bool):
+    select_raw, select_types = build_selects(PreferencesDemo)
+    values = [
+        {"preferencesdemo_id": 1, "preferencesdemo_tags": '["new"]'},
+        {"preferencesdemo_id": 2},
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, strict=False, validate=validate
+    )
+
+    assert result[0].settings == {}
+    assert result[0].tags == ["new"]
+    assert result[1].settings == {}
+    assert result[1].tags == []
+    # Every row gets its own copy of the default
+    assert result[0].settings is not result[1].settings
+
+
+@pytest.mark.parametrize("output_mode", ["model", "dict"])
+def test_default_json_for_null_values(output_mode: str):
+    select_raw, select_types = build_selects(PreferencesDemo, UserDemo.name)
+    values = [
+        {
+            "preferencesdemo_id": 1,
+            "preferencesdemo_settings": None,
+            "preferencesdemo_tags": "null",
+            "userdemo_name": "John",
+        },
+        {
+            "preferencesdemo_id": None,
+            "preferencesdemo_settings": None,
+            "preferencesdemo_tags": None,
+            "userdemo_name": "Jane",
+        },
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode=output_mode
+    )
+
+    selected = 0 if output_mode == "model" else "preferencesdemo"
+    assert dict(result[0][selected])["settings"] == {}
+    assert dict(result[0][selected])["tags"] == []
+    # Defaults don't count as values, so an all-NULL outer join row still collapses
+    assert result[1][selected] is None
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_msgpack_fields_use_unpackb(validate: bool):
+    select_raw, select_types = build_selects(PackedDemo)
+    # Stand-in for msgpack.unpackb, which decodes any bytes-like buffer
+    encoded = {
+        b"\x81\xa4tier\xa4gold": {"tier": "gold"},
+        b"\x81\xa6source\xa6import": {"source": "import"},
+    }
+    payloads: list[Any] = []
+
+    def unpackb(payload: Any) -> Any:
+        payloads.append(payload)
+        return encoded[bytes(payload)]
+
+    values = [
+        {
+            "packeddemo_id": 1,
+            "packeddemo_attributes": memoryview(b"\x81\xa4tier\xa4gold"),
+            "packeddemo_snapshot": b"\x81\xa6source\xa6import",
+        },
+        {
+            "packeddemo_id": 2,
+            "packeddemo_attributes": {"tier": "silver"},
+            "packeddemo_snapshot": None,
+        },
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, unpackb=unpackb, validate=validate
+    )
+
+    assert result[0].attributes == {"tier": "gold"}
+    assert result[0].snapshot == Metadata(source="import")
+    assert result[1].attributes == {"tier": "silver"}
+    assert result[1].snapshot is None
+    # Buffers reach the unpacker as-is, and already decoded values skip it
+    assert len(payloads) == 2
+    assert isinstance(payloads[0], memoryview)
+
+
+def test_msgpack_fields_require_unpackb():
+    select_raw, select_types = build_selects(PackedDemo)
+
+    with pytest.raises(ValueError, match="'attributes' of packeddemo .* unpackb"):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+def test_custom_json_loads_called_per_field():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": i,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": '{"source": "test"}',
+            "jsondemo_unique_val": f"value-{i}",
+        }
+        for i in range(3)
+    ]
+    calls: list[str] = []
+
+    def recording_loads(raw: str):
+        calls.append(raw)
+        return json_loads(raw)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, json_loads=recording_loads
+    )
+
+    assert len(calls) == 6
+    assert result[0].settings == {"theme": "dark"}
+    assert result[0].metadata == {"source": "test"}
+
+
+@pytest.mark.parametrize(
+    "payload, expected_message",
+    [
+        (datetime(2024, 1, 1), "expected JSON text, got datetime"),
+        (b'{"theme": "\xff"}', "invalid UTF-8 at byte 11"),
+        (memoryview(b"\xc3("), "invalid UTF-8 at byte 0"),
+    ],
+)
+def test_json_rejects_undecodable_payloads(payload: Any, expected_message: str):
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": {"theme": "dark"},
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        },
+        {
+            "jsondemo_id": 2,
+            "jsondemo_settings": payload,
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        },
+    ]
+
+    with pytest.raises(ValueError) as exc_info:
+        optimize_exec_casting(values, select_raw, select_types)
+
+    assert str(exc_info.value) == (
+        f"Invalid JSON for 'settings' in row 1 (select index 0): {expected_message}"
+    )
+
+
+@pytest.mark.parametrize("constant", ["NaN", "Infinity", "-Infinity"])
+def test_json_strict_rejects_non_finite(constant: str):
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": f'{{"ratio": {constant}}}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        }
+    ]
+
+    lenient = optimize_exec_casting(values, select_raw, select_types)
+    assert not isfinite(lenient[0].settings["ratio"])
+
+    with pytest.raises(NonFiniteJSONError, match=constant) as exc_info:
+        optimize_exec_casting(values, select_raw, select_types, json_strict=True)
+    assert "Raised while casting row 0 (select index 0)" in exc_info.value.__notes__
+
+
+def test_json_strict_requires_stdlib_loader():
+    with pytest.raises(ValueError, match="json_strict"):
+        optimize_exec_casting([], [], [], json_strict=True, json_loads=json_loads)
+
+
+def test_batch_json_matches_per_row_parsing():
+    select_raw, select_types = build_selects(JsonDemo, DocumentDemo)
+    values = [
+        {
+            "jsondemo_id": i,
+            "jsondemo_settings": json_dumps({"theme": "dark", "index": i}),
+            "jsondemo_metadata": None if i % 3 else json_dumps({"nested": [i]}),
+            "jsondemo_unique_val": f"value-{i}",
+            "documentdemo_id": i,
+            "documentdemo_metadata": json_dumps({"source": f"import-{i}"}),
+            "documentdemo_revisions": "[]" if i % 2 else '[{"source": "a"}]',
+        }
+        for i in range(10)
+    ]
+    calls: list[str] = []
+
+    def recording_loads(raw: str):
+        calls.append(raw)
+        return json_loads(raw)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, json_loads=recording_loads, batch_json=True
+    )
+
+    assert result == optimize_exec_casting(values, select_raw, select_types)
+    # One call per JSON field, instead of one per field and row
+    assert len(calls) == 4
+    assert result[1][0].settings is not result[2][0].settings
+
+
+@pytest.mark.parametrize(
+    "settings",
+    [
+        # Decoded by the driver already
+        {"theme": "dark"},
+        # Not valid on its own, but would shift the joined array
+        '"dark", "light"',
+    ],
+)
+def test_batch_json_falls_back_to_per_row_parsing(settings: Any):
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "light"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        },
+        {
+            "jsondemo_id": 2,
+            "jsondemo_settings": settings,
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        },
+    ]
+
+    if isinstance(settings, dict):
+        result = optimize_exec_casting(
+            values, select_raw, select_types, batch_json=True
+        )
+        assert [row.settings for row in result] == [{"theme": "light"}, settings]
+    else:
+        with pytest.raises(ValueError) as exc_info:
+            optimize_exec_casting(values, select_raw, select_types, batch_json=True)
+        assert "Raised while casting row 1 (select index 0)" in exc_info.value.__notes__
+
+
+def test_batch_json_prepared_select_reparses_each_batch():
+    select_raw, select_types = build_selects(JsonDemo)
+    prepared = PreparedSelect(select_raw, select_types, batch_json=True)
+
+    for page in range(2):
+        values = [
+            {
+                "jsondemo_id": page,
+                "jsondemo_settings": json_dumps({"page": page}),
+                "jsondemo_metadata": None,
+                "jsondemo_unique_val": "value",
+            }
+        ]
+        assert prepared.materialize(values)[0].settings == {"page": page}
+
+
+def test_custom_json_loads_skips_null():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": "{}",
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        }
+    ]
+    calls: list[str] = []
+
+    def recording_loads(raw: str):
+        calls.append(raw)
+        return json_loads(raw)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, json_loads=recording_loads
+    )
+
+    assert calls == ["{}"]
+    assert result[0].metadata is None
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_json_submodel_hydration(validate: bool):
+    select_raw, select_types = build_selects(DocumentDemo)
+    values = [
+        {
+            "documentdemo_id": 1,
+            "documentdemo_metadata": '{"source": "import", "version": 2}',
+            "documentdemo_revisions": '[{"source": "draft"}, {"source": "final"}]',
+        },
+        {
+            "documentdemo_id": 2,
+            "documentdemo_metadata": None,
+            "documentdemo_revisions": "[]",
+        },
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert isinstance(result[0].metadata, Metadata)
+    assert result[0].metadata.source == "import"
+    assert result[0].metadata.version == 2
+    assert [revision.source for revision in result[0].revisions] == ["draft", "final"]
+    assert all(isinstance(revision, Metadata) for revision in result[0].revisions)
+    assert result[1].metadata is None
+    assert result[1].revisions == []
+
+
+def test_json_submodel_validation():
+    select_raw, select_types = build_selects(DocumentDemo)
+    values = [
+        {
+            "documentdemo_id": 1,
+            "documentdemo_metadata": '{"version": "not-a-number"}',
+            "documentdemo_revisions": "[]",
+        }
+    ]
+
+    with pytest.raises(ValueError):
+        optimize_exec_casting(values, select_raw, select_types)
+
+    # Without validation the payload is trusted as-is
+    result = optimize_exec_casting(values, select_raw, select_types, validate=False)
+    assert result[0].metadata.version == "not-a-number"
+
+
+def test_json_already_parsed_passes_through():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "raw",
+        },
+        {
+            "jsondemo_id": 2,
+            "jsondemo_settings": {"theme": "dark"},
+            "jsondemo_metadata": {"source": "driver"},
+            "jsondemo_unique_val": "parsed",
+        },
+    ]
+    calls: list[str] = []
+
+    def recording_loads(raw: str):
+        calls.append(raw)
+        return json_loads(raw)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, json_loads=recording_loads
+    )
+
+    assert calls == ['{"theme": "dark"}']
+    assert result[0].settings == result[1].settings == {"theme": "dark"}
+    assert result[1].metadata == {"source": "driver"}
+
+
+@pytest.mark.parametrize(
+    "raw_document",
+    [
+        memoryview(b'{"kind": "binary"}'),
+        b'{"kind": "binary"}',
+        bytearray(b'{"kind": "binary"}'),
+    ],
+)
+def test_json_bytes_payload_decoded(raw_document: Any):
+    select_raw, select_types = build_selects(BinaryDemo)
+    values = [
+        {
+            "binarydemo_id": 1,
+            "binarydemo_payload": b"\x00\x01",
+            "binarydemo_document": raw_document,
+        }
+    ]
+    calls: list[Any] = []
+
+    def recording_loads(raw: Any):
+        calls.append(raw)
+        return json_loads(raw)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, json_loads=recording_loads
+    )
+
+    assert calls == ['{"kind": "binary"}']
+    assert result[0].document == {"kind": "binary"}
+    assert result[0].payload == b"\x00\x01"
+
+
+def test_binary_column_passes_through():
+    select_raw, select_types = build_selects(BinaryDemo.payload)
+    payload = memoryview(b"\x00\x01\x02")
+    values = [{"binarydemo_payload": payload}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result[0] is payload
+
+
+@pytest.mark.parametrize(
+    "binary_as_memoryview, raw_value, expected_type",
+    [
+        (None, memoryview(b"abc"), memoryview),
+        (None, b"abc", bytes),
+        (True, memoryview(b"abc"), memoryview),
+        (True, b"abc", memoryview),
+        (False, memoryview(b"abc"), bytes),
+        (False, bytearray(b"abc"), bytes),
+    ],
+)
+def test_binary_as_memoryview(
+    binary_as_memoryview: bool | None, raw_value: Any, expected_type: type
+):
+    select_raw, select_types = build_selects(BinaryDemo.payload)
+    values = [{"binarydemo_payload": raw_value}, {"binarydemo_payload": None}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, binary_as_memoryview=binary_as_memoryview
+    )
+
+    assert type(result[0]) is expected_type
+    assert bytes(result[0]) == b"abc"
+    assert result[1] is None
+    if expected_type is memoryview and isinstance(raw_value, memoryview):
+        # The driver's buffer is handed back as-is instead of being copied
+        assert result[0] is raw_value
+
+
+def test_binary_as_memoryview_table_fields():
+    select_raw, select_types = build_selects(BinaryDemo)
+    buffer = b"\x00\x01"
+    values = [
+        {
+            "binarydemo_id": 1,
+            "binarydemo_payload": buffer,
+            "binarydemo_document": '{"kind": "binary"}',
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, validate=False, binary_as_memoryview=True
+    )
+
+    assert isinstance(result[0].payload, memoryview)
+    assert result[0].payload.obj is buffer
+    assert result[0].document == {"kind": "binary"}
+
+
+#
+# Validation
+#
+
+
+@pytest.mark.parametrize("validate, expected_calls", [(True, ["John"]), (False, [])])
+def test_validate_toggles_validators(validate: bool, expected_calls: list[str]):
+    ValidatedDemo.validated_names.clear()
+    select_raw, select_types = build_selects(ValidatedDemo)
+    values = [{"validateddemo_id": 1, "validateddemo_name": "John"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, validate=validate
+    )
+
+    assert ValidatedDemo.validated_names == expected_calls
+    assert result[0].id == 1
+    assert result[0].name == "John"
+
+
+def test_validate_false_still_parses_json():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=False)
+
+    assert result[0].settings == {"theme": "dark"}
+    assert result[0].get_modified_attributes() == {}
+
+
+def test_construct_with_keyword_arguments_only():
+    KeywordDemo.init_args.clear()
+    select_raw, select_types = build_selects(KeywordDemo)
+    values = [{"keyworddemo_id": 1, "keyworddemo_name": "John"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [KeywordDemo(id=1, name="John")]
+    assert KeywordDemo.init_args[0] == ()
+
+
+def test_construct_with_positional_arguments():
+    select_raw, select_types = build_selects(PointDemo)
+    values = [
+        {"pointdemo_id": 1, "pointdemo_x": 3, "pointdemo_y": 4},
+        # Missing fields keep their slot with the declared default
+        {"pointdemo_id": 2, "pointdemo_x": 5},
+    ]
+
+    with pytest.raises(TypeError):
+        optimize_exec_casting(values[:1], select_raw, select_types)
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        construct_style="positional",
+        strict=False,
+    )
+
+    assert [(point.id, point.x, point.y) for point in result] == [(1, 3, 4), (2, 5, 0)]
+
+
+def test_positional_construct_requires_validating_models():
+    select_raw, select_types = build_selects(PointDemo)
+
+    with pytest.raises(ValueError, match="only applies to the validating model"):
+        optimize_exec_casting(
+            [], select_raw, select_types, construct_style="positional", validate=False
+        )
+
+
+#
+# Type coercion
+#
+
+
+@pytest.mark.parametrize(
+    "raw_value, expected",
+    [
+        ("2024-01-01T00:00:00Z", datetime(2024, 1, 1, tzinfo=timezone.utc)),
+        ("2024-01-01T00:00:00", datetime(2024, 1, 1)),
+        (datetime(2024, 1, 1, 12), datetime(2024, 1, 1, 12)),
+    ],
+)
+@pytest.mark.parametrize("validate", [True, False])
+def test_datetime_coercion(raw_value: Any, expected: datetime, validate: bool):
+    select_raw, select_types = build_selects(EventDemo)
+    values = [
+        {
+            "eventdemo_id": 1,
+            "eventdemo_created_at": raw_value,
+            "eventdemo_updated_at": None,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].created_at == expected
+    assert result[0].created_at.tzinfo == expected.tzinfo
+    assert result[0].updated_at is None
+
+
+def test_datetime_coercion_column():
+    select_raw, select_types = build_selects(EventDemo.id, EventDemo.updated_at)
+    values = [{"eventdemo_id": 1, "eventdemo_updated_at": "2024-01-01 00:00:00+00"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [(1, datetime(2024, 1, 1, tzinfo=timezone.utc))]
+
+
+def test_datetime_coercion_invalid():
+    select_raw, select_types = build_selects(EventDemo.created_at)
+    values = [{"eventdemo_created_at": "not a date"}]
+
+    with pytest.raises(ValueError, match="created_at.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_epoch_datetime_coercion(validate: bool):
+    select_raw, select_types = build_selects(EpochDemo)
+    values = [
+        {
+            "epochdemo_id": 1,
+            "epochdemo_created_at": 1_700_000_000,
+            "epochdemo_logged_at": 1_700_000_000_250,
+            "epochdemo_checkpoints": [0, 86_400.5],
+        },
+        {
+            "epochdemo_id": 2,
+            "epochdemo_created_at": "2024-01-01T00:00:00Z",
+            "epochdemo_logged_at": None,
+            "epochdemo_checkpoints": [],
+        },
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].created_at == datetime(
+        2023, 11, 14, 22, 13, 20, tzinfo=timezone.utc
+    )
+    assert result[0].logged_at == datetime(
+        2023, 11, 14, 22, 13, 20, 250_000, tzinfo=timezone.utc
+    )
+    assert result[0].checkpoints == [
+        datetime(1970, 1, 1, tzinfo=timezone.utc),
+        datetime(1970, 1, 2, 0, 0, 0, 500_000, tzinfo=timezone.utc),
+    ]
+    assert result[1].created_at == datetime(2024, 1, 1, tzinfo=timezone.utc)
+    assert result[1].logged_at is None
+
+
+def test_epoch_datetime_out_of_range():
+    select_raw, select_types = build_selects(EpochDemo.created_at)
+    values = [{"epochdemo_created_at": 10**20}]
+
+    with pytest.raises(ValueError, match="created_at.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_epoch_unit_requires_datetime():
+    class BadEpochDemo(TableBase):
+        id: int = Field(primary_key=True, default=None, epoch_unit="seconds")
+
+    select_raw, select_types = build_selects(BadEpochDemo)
+
+    with pytest.raises(ValueError, match="only applies to datetime fields"):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "raw_value, expected",
+    [
+        ("$1,234.56", Decimal("1234.56")),
+        ("($5.00)", Decimal("-5.00")),
+        ("-$5.00", Decimal("-5.00")),
+        ("10", Decimal("10")),
+        (Decimal("7.25"), Decimal("7.25")),
+        (3, Decimal("3")),
+    ],
+)
+def test_money_fields(raw_value: Any, expected: Decimal):
+    select_raw, select_types = build_selects(InvoiceDemo, InvoiceDemo.refund)
+    values = [
+        {
+            "invoicedemo_id": 1,
+            "invoicedemo_total": raw_value,
+            "invoicedemo_refund": None,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=False)
+
+    assert result == [(InvoiceDemo(id=1, total=expected), None)]
+    assert type(result[0][0].total) is Decimal
+
+
+@pytest.mark.parametrize("raw_value", ["$1.234,56", "$1,2,3", "$1,234.5,6", "($0,50)"])
+def test_money_fields_reject_malformed(raw_value: str):
+    select_raw, select_types = build_selects(InvoiceDemo.total)
+    values = [{"invoicedemo_total": raw_value}]
+
+    with pytest.raises(ValueError, match=r"'total' in row 0 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_money_format_is_configurable():
+    select_raw, select_types = build_selects(InvoiceDemo.total, InvoiceDemo.refund)
+    values = [{"invoicedemo_total": "1.234,56 €", "invoicedemo_refund": "(0,50 €)"}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        money_symbols=["€"],
+        money_separators=(".", ","),
+    )
+
+    assert result == [(Decimal("1234.56"), Decimal("-0.50"))]
+
+    # The default format doesn't know the symbol
+    with pytest.raises(ValueError, match=r"'total' in row 0 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_bit_fields(validate: bool):
+    select_raw, select_types = build_selects(BitDemo)
+    values = [
+        {
+            "bitdemo_id": 1,
+            "bitdemo_flags": "1010",
+            "bitdemo_mask": "1010",
+            "bitdemo_packed": "1010",
+        },
+        {
+            "bitdemo_id": 2,
+            "bitdemo_flags": "",
+            "bitdemo_mask": None,
+            "bitdemo_packed": "111111111",
+        },
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result == [
+        BitDemo(id=1, flags="1010", mask=10, packed=b"\xa0"),
+        BitDemo(id=2, flags="", mask=None, packed=b"\xff\x80"),
+    ]
+
+
+def test_bit_fields_reject_invalid_digits():
+    select_raw, select_types = build_selects(BitDemo.mask)
+
+    with pytest.raises(ValueError, match=r"'mask' in row 0 \(select index 0\): '10a1'"):
+        optimize_exec_casting([{"bitdemo_mask": "10a1"}], select_raw, select_types)
+
+
+def test_bit_requires_supported_type():
+    class BadBitDemo(TableBase):
+        id: int = Field(primary_key=True, default=None)
+        flags: list[bool] = Field(is_bit=True)
+
+    select_raw, select_types = build_selects(BadBitDemo)
+
+    with pytest.raises(ValueError, match="only applies to str, int, or bytes fields"):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+def test_money_requires_decimal():
+    class BadMoneyDemo(TableBase):
+        id: int = Field(primary_key=True, default=None)
+        total: float = Field(is_money=True)
+
+    select_raw, select_types = build_selects(BadMoneyDemo)
+
+    with pytest.raises(ValueError, match="only applies to Decimal fields"):
+        optimize_exec_casting([], select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "raw_value",
+    [
+        "12345678-1234-5678-1234-567812345678",
+        UUID("12345678-1234-5678-1234-567812345678"),
+    ],
+)
+def test_uuid_coercion(raw_value: Any):
+    select_raw, select_types = build_selects(TokenDemo)
+    values = [
+        {
+            "tokendemo_id": 1,
+            "tokendemo_token": raw_value,
+            "tokendemo_parent_token": None,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=False)
+
+    assert result[0].token == UUID("12345678-1234-5678-1234-567812345678")
+    assert result[0].parent_token is None
+
+
+@pytest.mark.parametrize(
+    "field, raw_value, expected",
+    [
+        (ScheduleDemo.day, "2024-01-01", date(2024, 1, 1)),
+        (ScheduleDemo.day, date(2024, 1, 1), date(2024, 1, 1)),
+        (ScheduleDemo.starts_at, "12:30:00", time(12, 30)),
+        (ScheduleDemo.starts_at, "08:15:30.25", time(8, 15, 30, 250000)),
+        (ScheduleDemo.starts_at, None, None),
+        (ScheduleDemo.duration, "00:00:05", timedelta(seconds=5)),
+        (ScheduleDemo.duration, "1 day 02:03:04", timedelta(days=1, seconds=7384)),
+        (ScheduleDemo.duration, "-3 days", timedelta(days=-3)),
+        (ScheduleDemo.duration, "-00:00:01.5", timedelta(seconds=-1.5)),
+        (ScheduleDemo.duration, "1 year 2 mons", timedelta(days=420)),
+        (ScheduleDemo.duration, "1 year", timedelta(days=360)),
+        (ScheduleDemo.duration, "12 mons", timedelta(days=360)),
+        (ScheduleDemo.duration, timedelta(hours=1), timedelta(hours=1)),
+    ],
+)
+def test_temporal_coercion(field: Any, raw_value: Any, expected: Any):
+    select_raw, select_types = build_selects(field)
+    values = [{f"scheduledemo_{field.key}": raw_value}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [expected]
+
+
+@pytest.mark.parametrize(
+    "field, raw_value",
+    [
+        (ScheduleDemo.day, "2024-13-01"),
+        (ScheduleDemo.starts_at, "noon"),
+        (ScheduleDemo.duration, "5 fortnights"),
+        (ScheduleDemo.duration, "02:03"),
+    ],
+)
+def test_temporal_coercion_invalid(field: Any, raw_value: str):
+    select_raw, select_types = build_selects(field)
+    values = [{f"scheduledemo_{field.key}": raw_value}]
+
+    with pytest.raises(ValueError, match=f"{field.key}.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+def test_uuid_coercion_column():
+    select_raw, select_types = build_selects(TokenDemo.token)
+    values = [{"tokendemo_token": "12345678-1234-5678-1234-567812345678"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [UUID("12345678-1234-5678-1234-567812345678")]
+
+
+def test_uuid_coercion_invalid():
+    select_raw, select_types = build_selects(TokenDemo.token)
+    values = [{"tokendemo_token": "not-a-uuid"}]
+
+    with pytest.raises(ValueError, match="token.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("raw_value", ["0.1", 0.1, Decimal("0.1")])
+def test_decimal_coercion(raw_value: Any):
+    select_raw, select_types = build_selects(PriceDemo)
+    values = [
+        {"pricedemo_id": 1, "pricedemo_amount": raw_value, "pricedemo_discount": None},
+        {"pricedemo_id": 2, "pricedemo_amount": "0.2", "pricedemo_discount": 5},
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=False)
+
+    assert result[0].amount + result[1].amount == Decimal("0.3")
+    assert result[0].discount is None
+    assert result[1].discount == Decimal(5)
+
+
+def test_decimal_coercion_invalid():
+    select_raw, select_types = build_selects(PriceDemo.amount)
+    values = [{"pricedemo_amount": "twelve"}]
+
+    with pytest.raises(ValueError, match="amount.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+@pytest.mark.parametrize(
+    "raw_value, expected",
+    [
+        ("1.5", 1.5),
+        ("-2e3", -2000.0),
+        ("Infinity", inf),
+        ("-Infinity", -inf),
+        (1.5, 1.5),
+        (2, 2),
+    ],
+)
+def test_float_coercion(raw_value: Any, expected: float, validate: bool):
+    select_raw, select_types = build_selects(MeasureDemo)
+    values = [
+        {
+            "measuredemo_id": 1,
+            "measuredemo_reading": raw_value,
+            "measuredemo_ratio": None,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].reading == expected
+    assert result[0].ratio is None
+
+
+def test_float_coercion_nan():
+    select_raw, select_types = build_selects(MeasureDemo.reading)
+    values = [{"measuredemo_reading": "NaN"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert isinstance(result[0], float)
+    assert isnan(result[0])
+
+
+def test_float_coercion_invalid():
+    select_raw, select_types = build_selects(MeasureDemo.reading)
+    values = [{"measuredemo_reading": "1.5"}, {"measuredemo_reading": "high"}]
+
+    with pytest.raises(ValueError, match=r"'reading' in row 1 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "raw_value, options",
+    [
+        ("1,234.56", {}),
+        ("1.234,56", {"numeric_separators": (".", ",")}),
+        ("1 234,56", {"numeric_separators": (" ", ",")}),
+    ],
+)
+def test_numeric_separators(raw_value: str, options: dict[str, Any]):
+    select_raw, select_types = build_selects(PriceDemo.amount, MeasureDemo.reading)
+    values = [{"pricedemo_amount": raw_value, "measuredemo_reading": raw_value}]
+
+    result = optimize_exec_casting(values, select_raw, select_types, **options)
+
+    assert result == [(Decimal("1234.56"), 1234.56)]
+
+
+@pytest.mark.parametrize(
+    "raw_value, options",
+    [
+        # Swapped formats would otherwise lose digits, like "1.234,56" as 1.23456
+        ("1.234,56", {}),
+        ("1,234.5,6", {}),
+        ("0,5", {}),
+        ("1,234.56", {"numeric_separators": (".", ",")}),
+        ("1.234.56", {"numeric_separators": (".", ",")}),
+        ("1,2,3", {"numeric_separators": (".", ",")}),
+    ],
+)
+def test_numeric_separators_reject_other_formats(
+    raw_value: str, options: dict[str, Any]
+):
+    for select_raw, select_types in (
+        build_selects(PriceDemo.amount),
+        build_selects(MeasureDemo.reading),
+    ):
+        values = [{"pricedemo_amount": raw_value, "measuredemo_reading": raw_value}]
+
+        with pytest.raises(ValueError, match=r"in row 0 \(select index 0\)"):
+            optimize_exec_casting(values, select_raw, select_types, **options)
+
+
+def test_numeric_separators_apply_to_money():
+    select_raw, select_types = build_selects(InvoiceDemo.total, PriceDemo.amount)
+    values = [{"invoicedemo_total": "$1.234,56", "pricedemo_amount": "0,5"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, numeric_separators=(".", ",")
+    )
+
+    assert result == [(Decimal("1234.56"), Decimal("0.5"))]
+
+    # European text doesn't read in the default format, money included
+    with pytest.raises(ValueError, match=r"'total' in row 0 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+    with pytest.raises(ValueError, match=r"'amount' in row 0 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw[1:], select_types[1:])
+
+
+def test_numeric_separators_must_differ():
+    select_raw, select_types = build_selects(PriceDemo.amount)
+
+    with pytest.raises(ValueError, match="numeric_separators"):
+        optimize_exec_casting(
+            [], select_raw, select_types, numeric_separators=(",", ",")
+        )
+
+
+@pytest.mark.parametrize(
+    "raw_value", ["/srv/assets/logo.png", Path("/srv/assets/logo.png")]
+)
+@pytest.mark.parametrize("validate", [True, False])
+def test_path_coercion(raw_value: Any, validate: bool):
+    select_raw, select_types = build_selects(AssetDemo)
+    values = [
+        {
+            "assetdemo_id": 1,
+            "assetdemo_location": raw_value,
+            "assetdemo_thumbnail": None,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert isinstance(result[0].location, Path)
+    assert result[0].location == Path("/srv/assets/logo.png")
+    assert result[0].thumbnail is None
+
+
+def test_path_coercion_column():
+    select_raw, select_types = build_selects(AssetDemo.thumbnail)
+    values = [{"assetdemo_thumbnail": "thumbs/logo.png"}, {"assetdemo_thumbnail": None}]
+
+    assert optimize_exec_casting(values, select_raw, select_types) == [
+        Path("thumbs/logo.png"),
+        None,
+    ]
+
+
+def test_path_coercion_invalid():
+    select_raw, select_types = build_selects(AssetDemo.location)
+    values = [{"assetdemo_location": "/srv"}, {"assetdemo_location": 42}]
+
+    with pytest.raises(ValueError, match=r"'location' in row 1 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize(
+    "field, raw_value, expected",
+    [
+        (ArrayDemo.scores, "{1,2,3}", [1, 2, 3]),
+        (ArrayDemo.scores, "{}", []),
+        (ArrayDemo.scores, "{NULL,1}", [None, 1]),
+        (ArrayDemo.scores, "[0:1]={4,5}", [4, 5]),
+        (ArrayDemo.scores, [1, 2, 3], [1, 2, 3]),
+        (ArrayDemo.labels, '{"a,b","c"}', ["a,b", "c"]),
+        (ArrayDemo.labels, '{"NULL",null}', ["NULL", None]),
+        (ArrayDemo.labels, r'{"say \"hi\"", plain }', ['say "hi"', "plain"]),
+        (ArrayDemo.grid, "{{1,2},{3,NULL}}", [[1, 2], [3, None]]),
+        (ArrayDemo.grid, "{{{1}},{{2}}}", [[[1]], [[2]]]),
+        (ArrayDemo.amounts, "{1.50,NULL,-2}", [Decimal("1.50"), None, Decimal("-2")]),
+        (ArrayDemo.amounts, '{"3.25",1e3}', [Decimal("3.25"), Decimal(1000)]),
+    ],
+)
+def test_array_coercion(field: Any, raw_value: Any, expected: list[Any]):
+    select_raw, select_types = build_selects(field)
+    values = [{f"arraydemo_{field.key}": raw_value}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [expected]
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_array_element_coercion_without_validation(validate: bool):
+    select_raw, select_types = build_selects(ArrayDemo)
+    values = [
+        {
+            "arraydemo_id": 1,
+            "arraydemo_scores": "{1,2}",
+            "arraydemo_labels": None,
+            "arraydemo_grid": "{{1,2},{3,4}}",
+            "arraydemo_amounts": "{0.10,NULL}",
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    # model_construct skips pydantic, so the leaves have to be converted while parsing
+    assert result[0].grid == [[1, 2], [3, 4]]
+    assert result[0].amounts == [Decimal("0.10"), None]
+    assert isinstance(result[0].amounts[0], Decimal)
+
+
+@pytest.mark.parametrize("raw_value", ["{1,2", "1,2}", "{1,two}", "{1}x"])
+def test_array_coercion_invalid(raw_value: str):
+    select_raw, select_types = build_selects(ArrayDemo.scores)
+    values = [{"arraydemo_scores": raw_value}]
+
+    with pytest.raises(ValueError, match="scores.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_enum_coercion(validate: bool):
+    select_raw, select_types = build_selects(TicketDemo)
+    values = [
+        {"ticketdemo_id": 1, "ticketdemo_status": "active", "ticketdemo_priority": 2},
+        {
+            "ticketdemo_id": 2,
+            "ticketdemo_status": Status.ARCHIVED,
+            "ticketdemo_priority": None,
+        },
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].status is Status.ACTIVE
+    assert result[0].priority is Priority.HIGH
+    assert result[1].status is Status.ARCHIVED
+    assert result[1].priority is None
+
+
+def test_enum_coercion_invalid():
+    select_raw, select_types = build_selects(TicketDemo.status)
+    values = [{"ticketdemo_status": "active"}, {"ticketdemo_status": "deleted"}]
+
+    with pytest.raises(
+        ValueError, match=r"'status' in row 1 \(select index 0\): 'deleted'"
+    ):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+@pytest.mark.parametrize(
+    "raw_value, expected",
+    [
+        (True, True),
+        (False, False),
+        (1, True),
+        (0, False),
+        ("t", True),
+        ("f", False),
+        ("true", True),
+        ("FALSE", False),
+        # The rest of what pydantic accepts
+        ("1", True),
+        ("0", False),
+        ("Yes", True),
+        ("no", False),
+        ("on", True),
+        ("off", False),
+        ("y", True),
+        ("N", False),
+        (1.0, True),
+        (0.0, False),
+    ],
+)
+def test_bool_coercion(raw_value: Any, expected: bool, validate: bool):
+    select_raw, select_types = build_selects(FlagDemo)
+    values = [
+        {"flagdemo_id": 1, "flagdemo_active": raw_value, "flagdemo_archived": None}
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert result[0].active is expected
+    assert result[0].archived is None
+
+
+@pytest.mark.parametrize("raw_value", [2, "2", "", " true", 0.5])
+def test_bool_coercion_invalid(raw_value: Any):
+    select_raw, select_types = build_selects(FlagDemo.active)
+    values = [{"flagdemo_active": True}, {"flagdemo_active": raw_value}]
+
+    with pytest.raises(ValueError, match=r"'active' in row 1 \(select index 0\)"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_registered_codec(validate: bool):
+    select_raw, select_types = build_selects(HostDemo)
+    values = [
+        {"hostdemo_id": 1, "hostdemo_address": "192.168.0.1"},
+        {"hostdemo_id": 2, "hostdemo_address": "::1"},
+        {"hostdemo_id": 3, "hostdemo_address": None},
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        validate=validate,
+        codecs={"inet": ip_address},
+    )
+
+    assert result[0].address == IPv4Address("192.168.0.1")
+    assert result[1].address == IPv6Address("::1")
+    assert result[2].address is None
+
+
+def test_registered_codec_column_select():
+    select_raw, select_types = build_selects(HostDemo.address)
+    values = [{"hostdemo_address": "10.0.0.1"}, {"hostdemo_address": "not an ip"}]
+
+    assert optimize_exec_casting(
+        values[:1], select_raw, select_types, codecs={"inet": ip_address}
+    ) == [IPv4Address("10.0.0.1")]
+
+    with pytest.raises(
+        ValueError, match=r"'address' in row 1 \(select index 0\): 'not an ip'"
+    ):
+        optimize_exec_casting(
+            values, select_raw, select_types, codecs={"inet": ip_address}
+        )
+
+    # Without a registered decoder the driver's value is returned as-is
+    assert optimize_exec_casting(values, select_raw, select_types) == [
+        "10.0.0.1",
+        "not an ip",
+    ]
+
+
+@pytest.mark.parametrize(
+    "field",
+    [
+        EventDemo.updated_at,
+        ScheduleDemo.day,
+        ScheduleDemo.starts_at,
+        ScheduleDemo.duration,
+        TokenDemo.parent_token,
+        PriceDemo.discount,
+        MeasureDemo.ratio,
+        FlagDemo.archived,
+        TicketDemo.priority,
+        ArrayDemo.labels,
+        AssetDemo.thumbnail,
+        BinaryDemo.payload,
+        HostDemo.address,
+    ],
+)
+def test_coercion_passes_null_through(field: Any):
+    select_raw, select_types = build_selects(field)
+    values = [{f"{field.root_model.get_table_name()}_{field.key}": None}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        binary_as_memoryview=True,
+        codecs={"inet": ip_address},
+    )
+
+    assert result == [None]
+
+
+#
+# Key prefixes
+#
+
+
+def test_key_prefixes_disambiguate_joined_tables():
+    select_raw, select_types = build_selects(UserDemo, PostDemo)
+    values = [
+        {
+            "users_id": 1,
+            "users_name": "John",
+            "users_email": "john@example.com",
+            "posts_id": 10,
+            "posts_name": "First post",
+            "posts_user_id": 1,
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        key_prefixes={UserDemo: "users_", PostDemo: "posts_"},
+    )
+
+    assert result == [
+        (
+            UserDemo(id=1, name="John", email="john@example.com"),
+            PostDemo(id=10, name="First post", user_id=1),
+        )
+    ]
+
+
+def test_key_prefixes_apply_to_columns():
+    select_raw, select_types = build_selects(UserDemo.name, PostDemo.name)
+    values = [{"users_name": "John", "posts_name": "First post"}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        key_prefixes={UserDemo: "users_", PostDemo: "posts_"},
+    )
+
+    assert result == [("John", "First post")]
+
+
+def test_table_aliases_hydrate_self_join():
+    manager = alias_table("manager", EmployeeDemo)
+    report = alias_table("report", EmployeeDemo)
+    select_raw, select_types = build_selects(manager, report)
+    values = [
+        {
+            "manager_id": 1,
+            "manager_name": "Alice",
+            "manager_manager_id": None,
+            "report_id": 2,
+            "report_name": "Bob",
+            "report_manager_id": 1,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [
+        (
+            EmployeeDemo(id=1, name="Alice", manager_id=None),
+            EmployeeDemo(id=2, name="Bob", manager_id=1),
+        )
+    ]
+    assert result[0][0] is not result[0][1]
+
+    assert optimize_exec_casting(
+        values, select_raw, select_types, output_mode="dict"
+    ) == [
+        {
+            "manager": {"id": 1, "name": "Alice", "manager_id": None},
+            "report": {"id": 2, "name": "Bob", "manager_id": 1},
+        }
+    ]
+
+
+def test_table_alias_key_prefixes():
+    manager = alias_table("manager", EmployeeDemo)
+    select_raw, select_types = build_selects(manager, EmployeeDemo)
+    values = [
+        {
+            "m_id": 1,
+            "m_name": "Alice",
+            "employeedemo_id": 2,
+            "employeedemo_name": "Bob",
+            "employeedemo_manager_id": 1,
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        strict=False,
+        key_prefixes={"manager": "m_"},
+    )
+
+    assert result == [
+        (
+            EmployeeDemo(id=1, name="Alice"),
+            EmployeeDemo(id=2, name="Bob", manager_id=1),
+        )
+    ]
+
+
+def test_unprefixed_keys_single_table():
+    select_raw, select_types = build_selects(UserDemo, UserDemo.email)
+    values = [{"id": 1, "name": "John", "email": "john@example.com"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [
+        (UserDemo(id=1, name="John", email="john@example.com"), "john@example.com")
+    ]
+
+
+def test_unprefixed_keys_rejected_for_joined_tables():
+    select_raw, select_types = build_selects(UserDemo, PostDemo)
+    values = [{"id": 1, "name": "John", "email": "john@example.com", "user_id": 1}]
+
+    with pytest.raises(KeyError, match="userdemo_id"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_aliased_fields_read_alias_keys(validate: bool):
+    select_raw, select_types = build_selects(AliasDemo)
+    values = [
+        {"aliasdemo_id": 1, "aliasdemo_uid": 5, "aliasdemo_display_name": "John"},
+        # Rows from the query builder still use the field names
+        {"aliasdemo_id": 2, "aliasdemo_user_id": 6, "aliasdemo_display": "Jane"},
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, validate=validate)
+
+    assert [(row.user_id, row.display) for row in result] == [(5, "John"), (6, "Jane")]
+
+
+def test_aliased_column_reads_alias_key():
+    select_raw, select_types = build_selects(AliasDemo.user_id)
+    values = [{"aliasdemo_uid": 5}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [5]
+
+
+def test_aliased_fields_dict_output_uses_field_names():
+    select_raw, select_types = build_selects(AliasDemo)
+    values = [{"aliasdemo_id": 1, "aliasdemo_uid": 5, "aliasdemo_display_name": "John"}]
+
+    result = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+
+    assert result == [{"id": 1, "user_id": 5, "display": "John"}]
+
+#
+# Transforms
+#
+
+
+def test_transform_uppercases_column():
+    select_raw, select_types = build_selects(UserDemo, PostDemo.name)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "postdemo_name": "first post",
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        transforms={"userdemo_name": str.upper, "postdemo_name": str.title},
+    )
+
+    assert result == [
+        (UserDemo(id=1, name="JOHN", email="john@example.com"), "First Post")
+    ]
+
+
+def test_transform_receives_null():
+    sentinel = object()
+    select_raw, select_types = build_selects(
+        ContactDemo.email, func.count(ContactDemo.id)
+    )
+    values = [
+        {"contactdemo_email": None, "aggregate_0": 1},
+        {"contactdemo_email": "john@example.com", "aggregate_0": 2},
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        transforms={
+            "contactdemo_email": lambda value: sentinel if value is None else value,
+            "aggregate_0": lambda value: value * 10,
+        },
+    )
+
+    assert result == [(sentinel, 10), ("john@example.com", 20)]
+
+
+def test_transform_runs_after_coercion():
+    select_raw, select_types = build_selects(EventDemo.created_at)
+    values = [{"eventdemo_created_at": "2024-01-01T00:00:00"}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        transforms={"eventdemo_created_at": lambda value: value.year},
+    )
+
+    assert result == [2024]
+
+
+#
+# Non-null fields
+#
+
+
+def test_non_null_fields_reject_nulls():
+    select_raw, select_types = build_selects(UserDemo, PostDemo.name)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "postdemo_name": "First post",
+        },
+        {
+            "userdemo_id": 2,
+            "userdemo_name": "Jane",
+            "userdemo_email": None,
+            "postdemo_name": None,
+        },
+    ]
+    options: dict[str, Any] = {"output_mode": "dict"}
+
+    with pytest.raises(ValueError, match=r"'email' in row 1 \(select index 0\)"):
+        optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            non_null_fields=["userdemo_email"],
+            **options,
+        )
+    with pytest.raises(ValueError, match=r"'name' in row 1 \(select index 1\)"):
+        optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            non_null_fields=["postdemo_name"],
+            **options,
+        )
+
+    # NULLs in other fields are fine
+    result = optimize_exec_casting(
+        values, select_raw, select_types, non_null_fields=["userdemo_name"], **options
+    )
+    assert result[1]["userdemo"]["email"] is None
+
+
+def test_non_null_fields_allow_null_tables():
+    select_raw, select_types = build_selects(UserDemo, PostDemo)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "postdemo_id": None,
+            "postdemo_name": None,
+            "postdemo_user_id": None,
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, non_null_fields=["postdemo_name"]
+    )
+
+    assert result == [(UserDemo(id=1, name="John", email="john@example.com"), None)]
+
+
+def test_non_null_fields_column_select():
+    select_raw, select_types = build_selects(UserDemo.id, func.count(UserDemo.id))
+    values = [{"userdemo_id": 1, "aggregate_0": None}]
+
+    with pytest.raises(ValueError, match=r"'aggregate_0' in row 0 \(select index 1\)"):
+        optimize_exec_casting(
+            values, select_raw, select_types, non_null_fields=["aggregate_0"]
+        )
+
+
+def test_non_null_fields_must_match_selects():
+    select_raw, select_types = build_selects(UserDemo.id)
+
+    with pytest.raises(ValueError, match=r"\['userdemo_nmae'\] don't match"):
+        optimize_exec_casting(
+            [], select_raw, select_types, non_null_fields=["userdemo_nmae"]
+        )
+
+
+#
+# Missing and null policies
+#
+
+# What each policy turns the `email` field of ContactDemo into, or the error it raises.
+# SKIPPED stands for a key left out of the output.
+SKIPPED = object()
+MISSING_OUTCOMES = {
+    "raise": KeyError,
+    "default": "unknown@example.com",
+    "none": None,
+    "skip": SKIPPED,
+}
+NULL_OUTCOMES = {
+    "raise": ValueError,
+    "default": "unknown@example.com",
+    "none": None,
+    "skip": SKIPPED,
+}
+
+
+@pytest.mark.parametrize(
+    "missing_policy, null_policy", list(product(MISSING_OUTCOMES, NULL_OUTCOMES))
+)
+def test_missing_and_null_policies(missing_policy: str, null_policy: str):
+    select_raw, select_types = build_selects(ContactDemo)
+    missing_row = {"contactdemo_id": 1, "contactdemo_name": "John"}
+    null_row = {**missing_row, "contactdemo_email": None}
+    options: dict[str, Any] = {
+        "output_mode": "dict",
+        "missing_policy": missing_policy,
+        "null_policy": null_policy,
+    }
+
+    # Each policy only governs its own case, whatever the other one is set to
+    for row, expected in (
+        (missing_row, MISSING_OUTCOMES[missing_policy]),
+        (null_row, NULL_OUTCOMES[null_policy]),
+    ):
+        if isinstance(expected, type):
+            with pytest.raises(expected, match="'email'"):
+                optimize_exec_casting([row], select_raw, select_types, **options)
+            continue
+
+        result = optimize_exec_casting([row], select_raw, select_types, **options)
+        if expected is SKIPPED:
+            assert result == [{"id": 1, "name": "John"}]
+        else:
+            assert result == [{"id": 1, "name": "John", "email": expected}]
+
+
+def test_skip_policies_defer_to_model():
+    select_raw, select_types = build_selects(ContactDemo)
+    values = [
+        {"contactdemo_id": 1, "contactdemo_name": "John"},
+        {"contactdemo_id": 2, "contactdemo_name": "Jane", "contactdemo_email": None},
+    ]
+
+    # Left out of the constructor call, so pydantic fills the declared default
+    result = optimize_exec_casting(
+        values, select_raw, select_types, missing_policy="skip", null_policy="skip"
+    )
+    assert result == [
+        ContactDemo(id=1, name="John", email="unknown@example.com"),
+        ContactDemo(id=2, name="Jane", email="unknown@example.com"),
+    ]
+
+    # Required fields are rejected by validation instead
+    with pytest.raises(ValidationError, match="name"):
+        optimize_exec_casting(
+            [{"contactdemo_id": 1}], select_raw, select_types, missing_policy="skip"
+        )
+
+
+def test_policies_for_column_selects():
+    select_raw, select_types = build_selects(ContactDemo.id, ContactDemo.email)
+    values = [{"contactdemo_id": None}]
+
+    # Columns have no default to fall back on, so every lenient policy gives None
+    for missing_policy, null_policy in product(("default", "none", "skip"), repeat=2):
+        assert optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            missing_policy=missing_policy,
+            null_policy=null_policy,
+        ) == [(None, None)]
+
+    with pytest.raises(ValueError, match=r"'id' in row 0 \(select index 0\)"):
+        optimize_exec_casting(
+            values, select_raw, select_types, missing_policy="none", null_policy="raise"
+        )
+
+
+def test_policies_keep_null_tables():
+    select_raw, select_types = build_selects(UserDemo, ContactDemo)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "contactdemo_id": None,
+            "contactdemo_name": None,
+        }
+    ]
+
+    for missing_policy, null_policy in product(("default", "none", "skip"), repeat=2):
+        result = optimize_exec_casting(
+            values,
+            select_raw,
+            select_types,
+            missing_policy=missing_policy,
+            null_policy=null_policy,
+        )
+        assert result == [(UserDemo(id=1, name="John", email="john@example.com"), None)]
+
+
+def test_missing_policy_overrides_strict():
+    select_raw, select_types = build_selects(ContactDemo.email)
+
+    assert optimize_exec_casting(
+        [{}], select_raw, select_types, strict=True, missing_policy="none"
+    ) == [None]
+    with pytest.raises(KeyError, match="contactdemo_email"):
+        optimize_exec_casting(
+            [{}], select_raw, select_types, strict=False, missing_policy="raise"
+        )
+    with pytest.raises(ValueError, match=r"Unknown null_policy 'drop'"):
+        optimize_exec_casting([{}], select_raw, select_types, null_policy="drop")
+
+
+#
+# Interrupts
+#
+
+
+# Mirrors the row loop's own check interval
+SIGNAL_CHECK_INTERVAL = 1024
+SIGNAL_ROW = 2_000
+
+
+@pytest.mark.parametrize("as_iterator", [False, True])
+def test_signal_interrupts_batch(as_iterator: bool):
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    # SIGUSR1 is ignored while the test runs, so only one row actually interrupts
+    values = [
+        {
+            "userdemo_id": SIGINT if i == SIGNAL_ROW else SIGUSR1,
+            "userdemo_name": str(i),
+        }
+        for i in range(5_000)
+    ]
+    seen: list[str] = []
+    previous_handler = signal(SIGUSR1, SIG_IGN)
+
+    try:
+        # Without signal checks the whole batch runs: interrupt_main only marks SIGINT
+        # as pending, and a C loop like this one reads every row before it's raised
+        signals = iter([row["userdemo_id"] for row in values])
+        with pytest.raises(KeyboardInterrupt):
+            deque(map(interrupt_main, signals), maxlen=0)
+        assert next(signals, None) is None
+
+        # Both transforms are C builtins, so only the row loop's own check can turn the
+        # pending signal into a KeyboardInterrupt before the batch ends
+        with pytest.raises(KeyboardInterrupt):
+            optimize_exec_casting(
+                iter(values) if as_iterator else values,
+                select_raw,
+                select_types,
+                transforms={
+                    "userdemo_id": interrupt_main,
+                    "userdemo_name": seen.append,
+                },
+            )
+    finally:
+        signal(SIGUSR1, previous_handler)
+
+    # The loop stops at its next check instead of casting the rest of the batch
+    assert SIGNAL_ROW < len(seen) <= SIGNAL_ROW + SIGNAL_CHECK_INTERVAL
+
+
+#
+# Timings
+#
+
+
+def test_collect_timings():
+    select_raw, select_types = build_selects(JsonDemo, UserDemo.name)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+            "userdemo_name": "John",
+        }
+    ]
+
+    result, timings = optimize_exec_casting(
+        values, select_raw, select_types, collect_timings=True
+    )
+
+    assert result == optimize_exec_casting(values, select_raw, select_types)
+    assert set(timings) == {"plan_build", "json_parse", "construct", "column_extract"}
+    assert all(elapsed >= 0 for elapsed in timings.values())
+    assert timings["json_parse"] > 0
+    assert timings["construct"] > 0
+
+
+@pytest.mark.parametrize(
+    "entrypoint", [optimize_exec_lenient, optimize_exec_columnar, optimize_exec_iter]
+)
+def test_collect_timings_rejected(entrypoint):
+    select_raw, select_types = build_selects(UserDemo.name)
+    values = [{"userdemo_name": "John"}]
+
+    with pytest.raises(ValueError, match=f"{entrypoint.__name__} doesn't return timings"):
+        entrypoint(values, select_raw, select_types, collect_timings=True)
+
+
+#
+# Provenance
+#
+
+
+def test_track_provenance():
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.name)
+    values = [
+        {"contactdemo_id": 1, "contactdemo_name": "John", "userdemo_name": "Jane"},
+        {"contactdemo_id": 2, "contactdemo_name": "Jim", "contactdemo_email": None},
+    ]
+
+    result, provenance = optimize_exec_casting(
+        iter(values), select_raw, select_types, strict=False, track_provenance=True
+    )
+
+    assert result == optimize_exec_casting(values, select_raw, select_types, strict=False)
+    assert result[0][0].email == "unknown@example.com"
+    assert provenance == [
+        ({"id": "present", "name": "present", "email": "default"}, "present"),
+        ({"id": "present", "name": "present", "email": "null"}, "missing"),
+    ]
+
+
+def test_track_provenance_with_timings():
+    select_raw, select_types = build_selects(UserDemo.name)
+    values = [{"userdemo_name": "John"}]
+
+    result, timings, provenance = optimize_exec_casting(
+        values, select_raw, select_types, collect_timings=True, track_provenance=True
+    )
+
+    assert result == ["John"]
+    assert timings["plan_build"] >= 0
+    assert provenance == [("present",)]
+
+
+@pytest.mark.parametrize(
+    "entrypoint", [optimize_exec_lenient, optimize_exec_typed, optimize_exec_iter]
+)
+def test_track_provenance_rejected(entrypoint):
+    select_raw, select_types = build_selects(UserDemo.name)
+    values = [{"userdemo_name": "John"}]
+
+    message = f"{entrypoint.__name__} doesn't return provenance"
+    with pytest.raises(ValueError, match=message):
+        entrypoint(values, select_raw, select_types, track_provenance=True)
+
+
+#
+# Case insensitive keys
+#
+
+
+def test_case_insensitive_keys():
+    select_raw, select_types = build_selects(UserDemo, func.count(UserDemo.id))
+    # Oracle-style results uppercase every unquoted identifier
+    values = [
+        {
+            "USERDEMO_ID": 1,
+            "USERDEMO_NAME": "John",
+            "UserDemo_Email": "john@example.com",
+            "AGGREGATE_0": 3,
+        }
+    ]
+
+    with pytest.raises(KeyError, match="userdemo_id"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, case_insensitive=True
+    )
+
+    assert result == [(UserDemo(id=1, name="John", email="john@example.com"), 3)]
+
+
+def test_case_insensitive_prefers_exact_match():
+    select_raw, select_types = build_selects(UserDemo.name)
+    values = [
+        {"USERDEMO_NAME": "upper", "userdemo_name": "exact"},
+        {"USERDEMO_NAME": "upper"},
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, case_insensitive=True
+    )
+
+    assert result == ["exact", "upper"]
+
+
+#
+# Extra columns
+#
+
+
+def test_extra_columns_passed_to_allowing_model():
+    select_raw, select_types = build_selects(RankedDemo)
+    values = [{"rankeddemo_id": 1, "rankeddemo_name": "John", "row_number": 1}]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [RankedDemo(id=1, name="John", row_number=1)]
+    assert result[0].row_number == 1
+
+
+def test_extra_columns_ignored_for_strict_model():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "row_number": 1,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [UserDemo(id=1, name="John", email="john@example.com")]
+    assert not hasattr(result[0], "row_number")
+
+
+def test_extra_columns_skip_keys_of_other_selects():
+    select_raw, select_types = build_selects(
+        RankedDemo, UserDemo, func.count(UserDemo.id)
+    )
+    values = [
+        {
+            "rankeddemo_id": 1,
+            "rankeddemo_name": "John",
+            "rankeddemo_row_number": 2,
+            "userdemo_id": 3,
+            "userdemo_name": "Jane",
+            "userdemo_email": "jane@example.com",
+            "aggregate_0": 4,
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    ranked, user, count = result[0]
+    assert ranked.model_extra == {"row_number": 2}
+    assert user == UserDemo(id=3, name="Jane", email="jane@example.com")
+    assert count == 4
+
+
+#
+# Row formats
+#
+
+
+class ReadOnlyRow(Mapping[str, Any]):
+    """
+    Mapping that only implements the abstract protocol, like SQLAlchemy's `RowMapping`.
+
+    """
+
+    def __init__(self, data: dict[str, Any]):
+        self._data = data
+
+    def __getitem__(self, key: str) -> Any:
+        return self._data[key]
+
+    def __iter__(self) -> Iterator[str]:
+        return iter(self._data)
+
+    def __len__(self) -> int:
+        return len(self._data)
+
+
+def test_mapping_rows():
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.email)
+    values = [
+        ReadOnlyRow(
+            {
+                "contactdemo_id": 1,
+                "CONTACTDEMO_NAME": "John",
+                "userdemo_email": "john@example.com",
+            }
+        )
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        strict=False,
+        case_insensitive=True,
+    )
+
+    assert result == [
+        (
+            ContactDemo(id=1, name="John", email="unknown@example.com"),
+            "john@example.com",
+        )
+    ]
+
+    with pytest.raises(KeyError, match="contactdemo_email"):
+        optimize_exec_casting(values, select_raw, select_types, case_insensitive=True)
+
+
+@pytest.mark.parametrize("row_format", ["dict", "tuple", "attr"])
+def test_row_formats(row_format: str):
+    select_raw, select_types = build_selects(
+        UserDemo, EventDemo.created_at, func.count(UserDemo.id)
+    )
+    values: list[Any] = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "aggregate_0": 3,
+        }
+    ]
+    if row_format == "tuple":
+        values = [tuple(row.values()) for row in values]
+    elif row_format == "attr":
+        values = [SimpleNamespace(**row) for row in values]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, row_format=row_format
+    )
+
+    assert result == [
+        (
+            UserDemo(id=1, name="John", email="john@example.com"),
+            datetime(2024, 1, 1),
+            3,
+        )
+    ]
+
+
+def test_tuple_rows_skip_excluded_field_slots():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo.name)
+    # The excluded `posts` relationship still occupies a slot in the selected columns
+    values = [(1, "John", None, "First post")]
+
+    result = optimize_exec_casting(values, select_raw, select_types, row_format="tuple")
+
+    assert result == [(AuthorDemo(id=1, name="John"), "First post")]
+
+
+def test_tuple_rows_missing_trailing_column():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [(1,)]
+
+    with pytest.raises(KeyError, match="userdemo_name.*row 0"):
+        optimize_exec_casting(values, select_raw, select_types, row_format="tuple")
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, row_format="tuple", strict=False
+    )
+    assert result == [(1, None)]
+
+
+def test_attr_rows_missing_attribute():
+    select_raw, select_types = build_selects(ContactDemo, UserDemo.name)
+    values = [SimpleNamespace(contactdemo_id=1, contactdemo_name="John")]
+
+    with pytest.raises(KeyError, match="'contactdemo_email' for field 'email'"):
+        optimize_exec_casting(values, select_raw, select_types, row_format="attr")
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, row_format="attr", strict=False
+    )
+    assert result == [(ContactDemo(id=1, name="John"), None)]
+
+
+def test_unknown_row_format():
+    select_raw, select_types = build_selects(UserDemo.id)
+
+    with pytest.raises(ValueError, match="row_format"):
+        optimize_exec_casting([], select_raw, select_types, row_format="list")
+
+
+#
+# Output modes
+#
+
+
+def test_dict_output_table():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+
+    assert result == [
+        {
+            "id": 1,
+            "settings": {"theme": "dark"},
+            "metadata": None,
+            "unique_val": "value",
+        }
+    ]
+
+
+def test_dict_output_columns_and_functions():
+    select_raw, select_types = build_selects(
+        UserDemo.name, func.count(UserDemo.id)
+    )
+    values = [{"userdemo_name": "John", "aggregate_0": 2}]
+
+    result = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+
+    assert result == [{"name": "John", "aggregate_0": 2}]
+
+
+def test_dict_output_mixed_nests_tables():
+    select_raw, select_types = build_selects(UserDemo, EventDemo.created_at)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+        }
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, output_mode="dict")
+
+    assert result == [
+        {
+            "userdemo": {"id": 1, "name": "John", "email": "john@example.com"},
+            "created_at": datetime(2024, 1, 1),
+        }
+    ]
+
+
+def test_unknown_output_mode():
+    select_raw, select_types = build_selects(UserDemo)
+
+    with pytest.raises(ValueError, match="output_mode"):
+        optimize_exec_casting([], select_raw, select_types, output_mode="rows")
+
+
+def test_columnar_output():
+    select_raw, select_types = build_selects(JsonDemo, func.count(JsonDemo.id))
+    values = [
+        {
+            "jsondemo_id": i,
+            "jsondemo_settings": f'{{"index": {i}}}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": f"value-{i}",
+            "aggregate_0": i * 10,
+        }
+        for i in range(3)
+    ]
+
+    result = optimize_exec_columnar(values, select_raw, select_types)
+
+    assert result == {
+        "id": [0, 1, 2],
+        "settings": [{"index": 0}, {"index": 1}, {"index": 2}],
+        "metadata": [None, None, None],
+        "unique_val": ["value-0", "value-1", "value-2"],
+        "aggregate_0": [0, 10, 20],
+    }
+
+
+def test_columnar_output_null_table():
+    select_raw, select_types = build_selects(UserDemo.name, EventDemo)
+    values = [
+        {
+            "userdemo_name": "John",
+            "eventdemo_id": 5,
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "eventdemo_updated_at": None,
+        },
+        {
+            "userdemo_name": "Jane",
+            "eventdemo_id": None,
+            "eventdemo_created_at": None,
+            "eventdemo_updated_at": None,
+        },
+    ]
+
+    result = optimize_exec_columnar(values, select_raw, select_types)
+
+    assert result == {
+        "name": ["John", "Jane"],
+        "id": [5, None],
+        "created_at": [datetime(2024, 1, 1), None],
+        "updated_at": [None, None],
+    }
+
+
+def test_rename_dict_output():
+    select_raw, select_types = build_selects(
+        EventDemo, UserDemo.name, func.count(UserDemo.id)
+    )
+    values = [
+        {
+            "eventdemo_id": 1,
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "eventdemo_updated_at": None,
+            "userdemo_name": "John",
+            "aggregate_0": 2,
+        }
+    ]
+    rename = {
+        "eventdemo": "event",
+        "created_at": "createdAt",
+        "name": "userName",
+        "aggregate_0": "total",
+    }
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="dict", rename=rename
+    )
+
+    assert result == [
+        {
+            "event": {"id": 1, "createdAt": datetime(2024, 1, 1), "updated_at": None},
+            "userName": "John",
+            "total": 2,
+        }
+    ]
+
+    # Model output keeps the declared field names
+    result = optimize_exec_casting(values, select_raw, select_types, rename=rename)
+
+    assert result == [(EventDemo(id=1, created_at=datetime(2024, 1, 1)), "John", 2)]
+
+
+def test_rename_columnar_output():
+    select_raw, select_types = build_selects(UserDemo.name, EventDemo)
+    values = [
+        {
+            "userdemo_name": "John",
+            "eventdemo_id": 5,
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "eventdemo_updated_at": None,
+        }
+    ]
+
+    result = optimize_exec_columnar(
+        values,
+        select_raw,
+        select_types,
+        rename={"name": "userName", "updated_at": "updatedAt"},
+    )
+
+    assert result == {
+        "userName": ["John"],
+        "id": [5],
+        "created_at": [datetime(2024, 1, 1)],
+        "updatedAt": [None],
+    }
+
+
+@pytest.mark.parametrize(
+    "on_duplicate, expected",
+    [
+        ("last", {"name": "John", "aggregate_0": 2}),
+        ("suffix", {"name": "John", "name_1": "John", "aggregate_0": 2}),
+    ],
+)
+def test_duplicate_dict_keys(on_duplicate: str, expected: dict[str, Any]):
+    select_raw, select_types = build_selects(
+        UserDemo.name, UserDemo.name, func.count(UserDemo.id)
+    )
+    values = [{"userdemo_name": "John", "aggregate_0": 2}]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        output_mode="dict",
+        on_duplicate=on_duplicate,
+    )
+
+    assert result == [expected]
+
+
+def test_duplicate_dict_keys_error():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name, UserDemo.name)
+    values = [{"userdemo_id": 1, "userdemo_name": "John"}]
+
+    with pytest.raises(ValueError, match=r"'name' \(select index 2\)"):
+        optimize_exec_casting(
+            values, select_raw, select_types, output_mode="dict", on_duplicate="error"
+        )
+
+    # Tuples keep every select by position
+    assert optimize_exec_casting(
+        values, select_raw, select_types, on_duplicate="error"
+    ) == [(1, "John", "John")]
+
+
+def test_duplicate_columnar_keys():
+    select_raw, select_types = build_selects(UserDemo, EventDemo.id, EventDemo.id)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_id": 5,
+        }
+    ]
+
+    result = optimize_exec_columnar(
+        values, select_raw, select_types, on_duplicate="suffix"
+    )
+
+    assert result == {
+        "id": [1],
+        "name": ["John"],
+        "email": ["john@example.com"],
+        "id_1": [5],
+        "id_2": [5],
+    }
+
+    with pytest.raises(ValueError, match=r"'id' \(select index 1\)"):
+        optimize_exec_columnar(values, select_raw, select_types, on_duplicate="error")
+
+
+def test_merged_dict_output():
+    select_raw, select_types = build_selects(
+        UserDemo, EventDemo, func.count(UserDemo.id)
+    )
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_id": None,
+            "eventdemo_created_at": None,
+            "eventdemo_updated_at": None,
+            "aggregate_0": 2,
+        }
+    ]
+
+    with pytest.raises(ValueError, match=r"'id' \(select index 1\)"):
+        optimize_exec_casting(
+            values, select_raw, select_types, output_mode="merged_dict"
+        )
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        output_mode="merged_dict",
+        prefix_table_keys=True,
+    )
+
+    # The unmatched table keeps its keys, so every row has the same shape
+    assert result == [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_id": None,
+            "eventdemo_created_at": None,
+            "eventdemo_updated_at": None,
+            "aggregate_0": 2,
+        }
+    ]
+
+
+def test_merged_dict_output_without_collisions():
+    select_raw, select_types = build_selects(UserDemo, PostDemo.user_id)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "postdemo_user_id": 1,
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="merged_dict"
+    )
+
+    assert result == [
+        {"id": 1, "name": "John", "email": "john@example.com", "user_id": 1}
+    ]
+
+
+def test_row_factory_builds_custom_objects():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"}
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, row_factory=lambda row: UserRecord(**row)
+    )
+
+    assert result == [UserRecord(id=1, name="John", email="j@example.com")]
+
+
+def test_row_factory_receives_nested_selects():
+    select_raw, select_types = build_selects(UserDemo, func.count(UserDemo.id))
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "j@example.com",
+            "aggregate_0": 3,
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        select_raw,
+        select_types,
+        row_factory=lambda row: (UserRecord(**row["userdemo"]), row["aggregate_0"]),
+    )
+
+    assert result == [(UserRecord(id=1, name="John", email="j@example.com"), 3)]
+
+    with pytest.raises(ValueError, match="namedtuple"):
+        optimize_exec_casting(
+            values, select_raw, select_types, output_mode="namedtuple", row_factory=dict
+        )
+
+
+def test_namedtuple_output_mode():
+    select_raw, select_types = build_selects(
+        UserDemo, EventDemo.created_at, func.count(UserDemo.id)
+    )
+    values = [
+        {
+            "userdemo_id": i,
+            "userdemo_name": f"User {i}",
+            "userdemo_email": f"user{i}@example.com",
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+            "aggregate_0": i * 2,
+        }
+        for i in range(3)
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="namedtuple"
+    )
+
+    assert result[1].userdemo.name == "User 1"
+    assert result[1].userdemo.email == "user1@example.com"
+    assert result[1].created_at == datetime(2024, 1, 1)
+    assert result[1].aggregate_0 == 2
+    assert not isinstance(result[1].userdemo, UserDemo)
+    # One type per call, shared by every row
+    assert len({type(row) for row in result}) == 1
+    assert len({type(row.userdemo) for row in result}) == 1
+
+
+def test_namedtuple_output_mode_single_table():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [{"userdemo_id": 1, "userdemo_name": "John"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="namedtuple", strict=False
+    )
+
+    assert result[0].id == 1
+    assert result[0].name == "John"
+    assert result[0].email is None
+    assert result[0]._fields == ("id", "name", "email")
+
+
+def test_namedtuple_output_mode_duplicate_names():
+    select_raw, select_types = build_selects(UserDemo.name, PostDemo.name)
+    values = [{"userdemo_name": "John", "postdemo_name": "First post"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="namedtuple"
+    )
+
+    assert result[0].name == "John"
+    assert tuple(result[0]) == ("John", "First post")
+
+
+#
+# Column selects
+#
+
+
+@pytest.mark.parametrize("strict", [True, False])
+def test_column_select_matches_general_path(strict: bool):
+    select_raw, select_types = build_selects(
+        EventDemo.id, EventDemo.created_at, EventDemo.updated_at
+    )
+    values = [
+        {
+            "eventdemo_id": 1,
+            "eventdemo_created_at": "2024-01-01T12:00:00",
+            "eventdemo_updated_at": None,
+        },
+        # Unprefixed keys resolve through the single table fallback
+        {"id": 2, "created_at": datetime(2024, 1, 2), "updated_at": None},
+    ]
+    if not strict:
+        values.append({"eventdemo_id": 3})
+
+    result = optimize_exec_casting(values, select_raw, select_types, strict=strict)
+
+    # Iteration always goes through the general row loop
+    assert result == list(
+        optimize_exec_iter(values, select_raw, select_types, strict=strict)
+    )
+    assert result[0] == (1, datetime(2024, 1, 1, 12), None)
+    assert result[1] == (2, datetime(2024, 1, 2), None)
+    if not strict:
+        assert result[2] == (3, None, None)
+
+
+def test_column_select_missing_key():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [{"userdemo_id": 1, "userdemo_name": "John"}, {"userdemo_id": 2}]
+
+    with pytest.raises(KeyError, match="userdemo_name.*row 1"):
+        optimize_exec_casting(values, select_raw, select_types)
+
+
+#
+# JSON output
+#
+
+
+def test_json_output_single_table():
+    select_raw, select_types = build_selects(JsonDemo)
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "a",
+        }
+    ]
+
+    result = optimize_exec_json(values, select_raw, select_types)
+
+    assert len(result) == 1
+    # JSON columns are nested as objects instead of double-encoded strings
+    assert json_loads(result[0]) == {
+        "id": 1,
+        "settings": {"theme": "dark"},
+        "metadata": None,
+        "unique_val": "a",
+    }
+
+
+def test_json_output_multiple_selects():
+    select_raw, select_types = build_selects(
+        EventDemo, TokenDemo.token, func.count(EventDemo.id)
+    )
+    token = UUID("12345678-1234-5678-1234-567812345678")
+    values = [
+        {
+            "eventdemo_id": 1,
+            "eventdemo_created_at": datetime(2024, 1, 1, 12),
+            "eventdemo_updated_at": None,
+            "tokendemo_token": str(token),
+            "aggregate_0": 2,
+        }
+    ]
+
+    result = optimize_exec_json(values, select_raw, select_types)
+
+    assert json_loads(result[0]) == {
+        "eventdemo": {
+            "id": 1,
+            "created_at": "2024-01-01T12:00:00",
+            "updated_at": None,
+        },
+        "token": str(token),
+        "aggregate_0": 2,
+    }
+
+
+def test_json_output_custom_serializer():
+    select_raw, select_types = build_selects(UserDemo.name, UserDemo.id)
+    values = [{"userdemo_name": "John", "userdemo_id": 1}]
+
+    result = optimize_exec_json(
+        values,
+        select_raw,
+        select_types,
+        dumps=lambda row: json_dumps(row, sort_keys=True),
+    )
+
+    assert result == ['{"id": 1, "name": "John"}']
+
+
+def test_json_output_rejects_other_output_modes():
+    select_raw, select_types = build_selects(UserDemo)
+
+    with pytest.raises(ValueError, match="dict output mode"):
+        optimize_exec_json([], select_raw, select_types, output_mode="model")
+
+
+#
+# Streaming
+#
+
+
+def test_casting_accepts_generator():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = ({"userdemo_id": i, "userdemo_name": f"User {i}"} for i in range(3))
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result == [(0, "User 0"), (1, "User 1"), (2, "User 2")]
+
+
+def test_casting_generator_keeps_row_index():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+
+    def rows():
+        yield {"userdemo_id": 1, "userdemo_name": "John"}
+        yield {"userdemo_id": 2}
+
+    with pytest.raises(KeyError, match="userdemo_name.*row 1"):
+        optimize_exec_casting(rows(), select_raw, select_types)
+
+
+def test_iter_is_lazy():
+    select_raw, select_types = build_selects(UserDemo)
+    pulled: list[int] = []
+
+    def rows():
+        for i in range(3):
+            pulled.append(i)
+            yield {
+                "userdemo_id": i,
+                "userdemo_name": f"User {i}",
+                "userdemo_email": f"user{i}@example.com",
+            }
+
+    iterator = optimize_exec_iter(rows(), select_raw, select_types)
+    assert pulled == []
+
+    first = next(iterator)
+    assert first == UserDemo(id=0, name="User 0", email="user0@example.com")
+    assert pulled == [0]
+
+    assert [user.id for user in iterator] == [1, 2]
+
+
+def test_iter_keeps_row_index():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [
+        {"userdemo_id": 1, "userdemo_name": "John"},
+        {"userdemo_id": 2},
+    ]
+
+    iterator = optimize_exec_iter(values, select_raw, select_types)
+    assert next(iterator) == (1, "John")
+
+    with pytest.raises(KeyError, match="row 1"):
+        next(iterator)
+
+
+#
+# Lazy models
+#
+
+
+def test_lazy_models_build_on_first_access():
+    ValidatedDemo.validated_names.clear()
+    select_raw, select_types = build_selects(ValidatedDemo, UserDemo.name)
+    values = [
+        {"validateddemo_id": i, "validateddemo_name": f"User {i}", "userdemo_name": "x"}
+        for i in range(3)
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, lazy=True)
+
+    assert all(isinstance(row[0], LazyModel) for row in result)
+    assert ValidatedDemo.validated_names == []
+
+    assert result[1][0].name == "User 1"
+    assert result[1][0].id == 1
+    # Only the row that was read is built, and only once
+    assert ValidatedDemo.validated_names == ["User 1"]
+    assert result[1][0] == ValidatedDemo(id=1, name="User 1")
+    assert result[0][1] == "x"
+
+
+def test_lazy_models_without_validation():
+    ValidatedDemo.validated_names.clear()
+    select_raw, select_types = build_selects(ValidatedDemo)
+    values = [{"validateddemo_id": 1, "validateddemo_name": "John"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, lazy=True, validate=False
+    )
+
+    assert result[0].name == "John"
+    assert result[0].get_modified_attributes() == {}
+    assert ValidatedDemo.validated_names == []
+
+
+def test_lazy_models_defer_validation_errors():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [{"userdemo_id": "not a number", "userdemo_name": "John"}]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, lazy=True, strict=False
+    )
+
+    with pytest.raises(ValidationError):
+        result[0].id
+
+
+def test_lazy_models_require_model_output():
+    with pytest.raises(ValueError, match="lazy"):
+        optimize_exec_casting([], [], [], lazy=True, output_mode="dict")
+
+
+#
+# Frozen models
+#
+
+
+@pytest.mark.parametrize(
+    "options",
+    [{}, {"validate": False}, {"dedup_by_pk": True}],
+)
+def test_freeze_rejects_mutation(options: dict[str, Any]):
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"}
+    ]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, freeze=True, **options
+    )
+
+    with pytest.raises(FrozenModelError, match="UserDemo"):
+        result[0].name = "Jane"
+    assert result[0].name == "John"
+    assert result[0].get_modified_attributes() == {}
+
+
+def test_unfrozen_models_stay_mutable():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"}
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+    result[0].name = "Jane"
+
+    assert result[0].get_modified_attributes() == {"name": "Jane"}
+
+
+def test_freeze_unfreezable_models():
+    values = [{"legacydemo_id": 1, "legacydemo_fullName": "John"}]
+    options: dict[str, Any] = {"freeze": True, "strict": False}
+
+    result = optimize_exec_casting(
+        values, [LegacyDemo], [(True, False, False)], **options
+    )
+    result[0].data = {}
+
+    with pytest.raises(ValueError, match="Can't freeze LegacyDemo"):
+        optimize_exec_casting(
+            values,
+            [LegacyDemo],
+            [(True, False, False)],
+            on_unfreezable="error",
+            **options,
+        )
+
+
+def test_freeze_requires_model_output():
+    with pytest.raises(ValueError, match="freeze"):
+        optimize_exec_casting([], [], [], freeze=True, output_mode="dict")
+
+
+#
+# Primary key deduplication
+#
+
+
+def test_dedup_by_pk_returns_same_instance():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"},
+        {"userdemo_id": 2, "userdemo_name": "Jane", "userdemo_email": "n@example.com"},
+        {"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"},
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, dedup_by_pk=True)
+
+    assert result[0] is result[2]
+    assert result[0] is not result[1]
+
+    result = optimize_exec_casting(values, select_raw, select_types)
+
+    assert result[0] == result[2]
+    assert result[0] is not result[2]
+
+
+def test_dedup_by_pk_in_joins():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo)
+    values = [
+        {
+            "authordemo_id": 1,
+            "authordemo_name": "Ann",
+            "postdemo_id": post_id,
+            "postdemo_name": f"Post {post_id}",
+            "postdemo_user_id": 1,
+        }
+        for post_id in range(3)
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, dedup_by_pk=True)
+
+    authors = [author for author, _ in result]
+    assert authors[0] is authors[1] is authors[2]
+    assert len({id(post) for _, post in result}) == 3
+
+
+def test_dedup_by_pk_skips_null_keys():
+    select_raw, select_types = build_selects(UserDemo)
+    row = {"userdemo_id": None, "userdemo_name": "John", "userdemo_email": "j@b.com"}
+    values = [row, dict(row)]
+
+    result = optimize_exec_casting(
+        values, select_raw, select_types, output_mode="dict", dedup_by_pk=True
+    )
+
+    assert result[0] == result[1]
+    assert result[0] is not result[1]
+
+
+#
+# Appending into lists
+#
+
+
+def test_exec_into_appends_batches():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    out: list[Any] = []
+
+    first_count = optimize_exec_into(
+        [{"userdemo_id": 1, "userdemo_name": "John"}], select_raw, select_types, out
+    )
+    second_count = optimize_exec_into(
+        iter(
+            [
+                {"userdemo_id": 2, "userdemo_name": "Jane"},
+                {"userdemo_id": 3, "userdemo_name": "Jim"},
+            ]
+        ),
+        select_raw,
+        select_types,
+        out,
+    )
+
+    assert (first_count, second_count) == (1, 2)
+    assert out == [(1, "John"), (2, "Jane"), (3, "Jim")]
+
+
+def test_exec_into_keeps_rows_before_error():
+    select_raw, select_types = build_selects(UserDemo.id)
+    out: list[Any] = [0]
+    values = [{"userdemo_id": 1}, {}]
+
+    with pytest.raises(KeyError, match=r"in row 1 \(select index 0\)"):
+        optimize_exec_into(values, select_raw, select_types, out)
+
+    assert out == [0, 1]
+
+
+#
+# Keyed maps
+#
+
+
+def build_user_rows(*ids: int) -> list[dict[str, Any]]:
+    return [
+        {
+            "userdemo_id": user_id,
+            "userdemo_name": f"User {i}",
+            "userdemo_email": f"user{i}@example.com",
+        }
+        for i, user_id in enumerate(ids)
+    ]
+
+
+def test_map_by_key_field():
+    select_raw, select_types = build_selects(UserDemo)
+
+    result = optimize_exec_map(build_user_rows(1, 2), select_raw, select_types, "id")
+
+    assert result == {
+        1: UserDemo(id=1, name="User 0", email="user0@example.com"),
+        2: UserDemo(id=2, name="User 1", email="user1@example.com"),
+    }
+
+
+@pytest.mark.parametrize(
+    "on_duplicate_key, expected",
+    [
+        ("last", {1: "User 1", 2: "User 2"}),
+        ("list", {1: ["User 0", "User 1"], 2: ["User 2"]}),
+    ],
+)
+def test_map_duplicate_keys(on_duplicate_key: str, expected: dict[int, Any]):
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+
+    result = optimize_exec_map(
+        [
+            {"userdemo_id": 1, "userdemo_name": "User 0"},
+            {"userdemo_id": 1, "userdemo_name": "User 1"},
+            {"userdemo_id": 2, "userdemo_name": "User 2"},
+        ],
+        select_raw,
+        select_types,
+        "id",
+        on_duplicate_key=on_duplicate_key,
+    )
+
+    if on_duplicate_key == "list":
+        assert result == {
+            key: [(key, name) for name in names] for key, names in expected.items()
+        }
+    else:
+        assert result == {key: (key, name) for key, name in expected.items()}
+
+
+def test_map_duplicate_keys_error():
+    select_raw, select_types = build_selects(UserDemo)
+
+    with pytest.raises(ValueError, match=r"Duplicate key 1 .* in row 2"):
+        optimize_exec_map(
+            build_user_rows(1, 2, 1),
+            select_raw,
+            select_types,
+            "id",
+            on_duplicate_key="error",
+        )
+
+
+def test_map_key_field_resolution():
+    select_raw, select_types = build_selects(UserDemo, PostDemo)
+    values = [
+        {
+            **build_user_rows(1)[0],
+            "postdemo_id": 10,
+            "postdemo_name": "First post",
+            "postdemo_user_id": 1,
+        }
+    ]
+
+    result = optimize_exec_map(values, select_raw, select_types, "postdemo_id")
+    assert list(result) == [10]
+
+    with pytest.raises(ValueError, match="matches 2 selected fields"):
+        optimize_exec_map(values, select_raw, select_types, "id")
+    with pytest.raises(ValueError, match="'uuid' doesn't match any selected field"):
+        optimize_exec_map(values, select_raw, select_types, "uuid")
+
+
+@pytest.mark.parametrize("output_mode", ["model", "dict", "namedtuple"])
+def test_map_reads_key_once(output_mode: str):
+    select_raw, select_types = build_selects(UserDemo)
+    values = [{"userdemo_name": "John"}, {"userdemo_name": "Jane"}]
+    calls: list[tuple[str, int]] = []
+
+    def placeholder(field_name: str, row_index: int) -> Any:
+        calls.append((field_name, row_index))
+        return row_index + 1 if field_name == "id" else f"{field_name}@example.com"
+
+    result = optimize_exec_map(
+        iter(values),
+        select_raw,
+        select_types,
+        "id",
+        strict=False,
+        output_mode=output_mode,
+        on_missing=placeholder,
+    )
+
+    assert list(result) == [1, 2]
+    assert calls == [("id", 0), ("email", 0), ("id", 1), ("email", 1)]
+
+
+#
+# Counting rows
+#
+
+
+@pytest.mark.parametrize("as_iterator", [False, True])
+def test_count_rows(as_iterator: bool):
+    values = [{"userdemo_id": i} for i in range(5)]
+    rows = iter(values) if as_iterator else values
+
+    assert optimize_exec_count(rows) == 5
+
+
+@pytest.mark.parametrize("as_iterator", [False, True])
+def test_count_rows_matching_predicate(as_iterator: bool):
+    values = [
+        {"userdemo_id": i, "userdemo_name": None if i % 2 else "John"} for i in range(5)
+    ]
+    rows = iter(values) if as_iterator else values
+    seen: list[Any] = []
+
+    def has_name(row: dict[str, Any]) -> bool:
+        seen.append(row)
+        return row["userdemo_name"] is not None
+
+    assert optimize_exec_count(rows, has_name) == 3
+    # The predicate sees every raw row as-is
+    assert seen == values
+
+
+def test_count_empty_rows():
+    assert optimize_exec_count([]) == 0
+    assert optimize_exec_count(iter([]), lambda row: True) == 0
+
+
+#
+# Typed results
+#
+
+
+def test_typed_schema_for_mixed_select():
+    select_raw, select_types = build_selects(
+        UserDemo, PostDemo.name, func.count(PostDemo.id)
+    )
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "postdemo_name": "First post",
+            "aggregate_0": 3,
+        }
+    ]
+
+    result, schema = optimize_exec_typed(values, select_raw, select_types)
+
+    assert result == [
+        (UserDemo(id=1, name="John", email="john@example.com"), "First post", 3)
+    ]
+    assert schema == [
+        ("userdemo", "table", UserDemo),
+        ("name", "column", str),
+        ("aggregate_0", "function", Any),
+    ]
+    assert [entry.kind for entry in schema] == ["table", "column", "function"]
+
+
+def test_typed_schema_without_rows():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.email)
+
+    result, schema = optimize_exec_typed([], select_raw, select_types)
+
+    assert result == []
+    assert schema == [("id", "column", int), ("email", "column", str)]
+
+
+#
+# Typed dicts
+#
+
+
+def test_typed_dict_select():
+    values = [
+        {
+            "profilerow_id": 1,
+            "profilerow_name": "John",
+            "profilerow_settings": '{"theme": "dark"}',
+            "profilerow_nickname": None,
+            "profilerow_unmapped": "ignored",
+        }
+    ]
+
+    result = optimize_exec_casting(values, [ProfileRow], [(True, False, False)])
+
+    # Plain dicts, with only the declared keys and the JSON key parsed
+    assert result == [
+        {"id": 1, "name": "John", "settings": {"theme": "dark"}, "nickname": None}
+    ]
+    assert type(result[0]) is dict
+
+
+def test_typed_dict_alongside_models():
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "profilerow_id": 2,
+            "profilerow_name": "Jane",
+            "profilerow_settings": "{}",
+            "profilerow_nickname": "jj",
+        }
+    ]
+
+    result = optimize_exec_casting(
+        values,
+        [*select_raw, ProfileRow],
+        [*select_types, (True, False, False)],
+        validate=False,
+    )
+
+    assert result == [
+        (
+            UserDemo(id=1, name="John", email="john@example.com"),
+            {"id": 2, "name": "Jane", "settings": {}, "nickname": "jj"},
+        )
+    ]
+
+
+def test_typed_dict_rows_are_not_shared():
+    values = [
+        {
+            "profilerow_id": i,
+            "profilerow_name": f"user-{i}",
+            "profilerow_settings": "{}",
+            "profilerow_nickname": None,
+        }
+        for i in range(2)
+    ]
+
+    result = optimize_exec_casting(values, [ProfileRow], [(True, False, False)])
+
+    assert [row["id"] for row in result] == [0, 1]
+    assert result[0] is not result[1]
+
+
+def test_typed_dict_leaves_out_missing_optional_keys():
+    values = [
+        {"profilerow_id": 1, "profilerow_name": "John", "profilerow_settings": "{}"},
+        {
+            "profilerow_id": 2,
+            "profilerow_name": "Jane",
+            "profilerow_settings": "{}",
+            "profilerow_nickname": "jj",
+        },
+    ]
+
+    result = optimize_exec_casting(values, [ProfileRow], [(True, False, False)])
+
+    # Absent from the row, like the key is allowed to be absent from the typed dict
+    assert result == [
+        {"id": 1, "name": "John", "settings": {}},
+        {"id": 2, "name": "Jane", "settings": {}, "nickname": "jj"},
+    ]
+
+    # Required keys are still required
+    with pytest.raises(KeyError, match="'name'"):
+        optimize_exec_casting(
+            [{"profilerow_id": 1, "profilerow_settings": "{}"}],
+            [ProfileRow],
+            [(True, False, False)],
+        )
+
+
+#
+# Explain
+#
+
+
+def test_explain_mixed_select():
+    select_raw, select_types = build_selects(
+        EnvelopeDemo, PriceDemo.discount, func.count(UserDemo.id)
+    )
+
+    explained = optimize_exec_explain(
+        select_raw, select_types, transforms={"aggregate_0": abs}
+    )
+
+    plain_field = {
+        "is_json": False,
+        "is_msgpack": False,
+        "json_path": None,
+        "codec": None,
+        "element_codec": None,
+        "submodel": None,
+        "coercions": (),
+        "non_null": False,
+        "transform": False,
+    }
+    assert explained == [
+        {
+            "position": 0,
+            "kind": "table",
+            "name": "envelopedemo",
+            "fields": [
+                {
+                    **plain_field,
+                    "name": "id",
+                    "keys": ("envelopedemo_id",),
+                    "column_index": 0,
+                    "output_key": "id",
+                },
+                {
+                    **plain_field,
+                    "name": "user_id",
+                    "keys": ("envelopedemo_user_id",),
+                    "column_index": 1,
+                    "output_key": "user_id",
+                    "is_json": True,
+                    "json_path": "user.id",
+                    "codec": "uuid",
+                    "coercions": ("decode", "path", "codec"),
+                },
+                {
+                    **plain_field,
+                    "name": "origin",
+                    "keys": ("envelopedemo_origin",),
+                    "column_index": 2,
+                    "output_key": "origin",
+                    "is_json": True,
+                    "json_path": "meta.origin",
+                    "submodel": Metadata,
+                    "coercions": ("decode", "path", "submodel"),
+                },
+            ],
+        },
+        {
+            "position": 1,
+            "kind": "column",
+            "name": "discount",
+            "fields": [
+                {
+                    **plain_field,
+                    "name": "discount",
+                    "keys": ("pricedemo_discount",),
+                    "column_index": 3,
+                    "output_key": "discount",
+                    "codec": "decimal",
+                }
+            ],
+        },
+        {
+            "position": 2,
+            "kind": "function",
+            "name": "aggregate_0",
+            "fields": [
+                {
+                    **plain_field,
+                    "name": "aggregate_0",
+                    "keys": ("aggregate_0",),
+                    "column_index": 4,
+                    "output_key": "aggregate_0",
+                    "transform": True,
+                }
+            ],
+        },
+    ]
+
+
+def test_explain_reflects_options():
+    select_raw, select_types = build_selects(ContactDemo)
+
+    explained = optimize_exec_explain(
+        select_raw,
+        select_types,
+        output_mode="dict",
+        rename={"email": "emailAddress"},
+        key_prefixes={ContactDemo: "c_"},
+    )
+
+    assert [
+        (field["keys"], field["output_key"]) for field in explained[0]["fields"]
+    ] == [
+        (("c_id", "id"), "id"),
+        (("c_name", "name"), "name"),
+        (("c_email", "email"), "emailAddress"),
+    ]
+
+
+#
+# Chunked casting
+#
+
+
+def test_chunked_reports_progress():
+    select_raw, select_types = build_selects(UserDemo.id, UserDemo.name)
+    values = [{"userdemo_id": i, "userdemo_name": f"User {i}"} for i in range(10)]
+    calls: list[tuple[int, int | None]] = []
+
+    result = optimize_exec_chunked(
+        values,
+        select_raw,
+        select_types,
+        chunk_size=4,
+        progress=lambda done, total: calls.append((done, total)),
+    )
+
+    assert result == optimize_exec_casting(values, select_raw, select_types)
+    assert calls == [(4, 10), (8, 10), (10, 10)]
+
+
+def test_chunked_generator_has_no_total():
+    select_raw, select_types = build_selects(UserDemo.id)
+    calls: list[tuple[int, int | None]] = []
+
+    result = optimize_exec_chunked(
+        ({"userdemo_id": i} for i in range(4)),
+        select_raw,
+        select_types,
+        chunk_size=2,
+        progress=lambda done, total: calls.append((done, total)),
+    )
+
+    assert result == [0, 1, 2, 3]
+    assert calls == [(2, None), (4, None)]
+
+
+def test_chunked_rejects_empty_chunks():
+    select_raw, select_types = build_selects(UserDemo.id)
+
+    assert optimize_exec_chunked([], select_raw, select_types, chunk_size=2) == []
+    with pytest.raises(ValueError, match="chunk_size"):
+        optimize_exec_chunked([], select_raw, select_types, chunk_size=0)
+
+
+#
+# Limited casting
+#
+
+
+@pytest.mark.parametrize("as_iterator", [False, True])
+def test_limited_casts_first_rows(as_iterator: bool):
+    select_raw, select_types = build_selects(UserDemo)
+    values = [
+        {"userdemo_id": i, "userdemo_name": f"User {i}", "userdemo_email": "a@b.com"}
+        for i in range(5)
+    ]
+    rows = iter(values) if as_iterator else values
+
+    result, has_more = optimize_exec_limited(rows, select_raw, select_types, limit=2)
+
+    assert [user.id for user in result] == [0, 1]
+    assert has_more
+    if as_iterator:
+        # Only the row that was peeked at is consumed past the limit
+        assert next(rows)["userdemo_id"] == 3
+
+
+@pytest.mark.parametrize("as_iterator", [False, True])
+@pytest.mark.parametrize("limit", [2, 3])
+def test_limited_without_more_rows(as_iterator: bool, limit: int):
+    select_raw, select_types = build_selects(UserDemo.id)
+    values = [{"userdemo_id": 1}, {"userdemo_id": 2}]
+    rows = iter(values) if as_iterator else values
+
+    assert optimize_exec_limited(rows, select_raw, select_types, limit=limit) == (
+        [1, 2],
+        False,
+    )
+
+
+def test_limited_rejects_negative_limit():
+    select_raw, select_types = build_selects(UserDemo.id)
+
+    assert optimize_exec_limited([], select_raw, select_types, limit=0) == ([], False)
+    with pytest.raises(ValueError, match="limit"):
+        optimize_exec_limited([], select_raw, select_types, limit=-1)
+
+
+#
+# Grouped joins
+#
+
+
+def build_author_rows(
+    *rows: tuple[int, str, int | None, str | None],
+) -> list[dict[str, Any]]:
+    return [
+        {
+            "authordemo_id": author_id,
+            "authordemo_name": author_name,
+            "postdemo_id": post_id,
+            "postdemo_name": post_name,
+            "postdemo_user_id": author_id if post_id is not None else None,
+        }
+        for author_id, author_name, post_id, post_name in rows
+    ]
+
+
+@pytest.mark.parametrize("validate", [True, False])
+def test_grouped_collapses_children(validate: bool):
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo)
+    values = build_author_rows(
+        (1, "John", 10, "First"),
+        (1, "John", 11, "Second"),
+        (2, "Jane", None, None),
+        (3, "Jim", 12, "Third"),
+    )
+
+    result = optimize_exec_grouped(
+        values,
+        select_raw,
+        select_types,
+        AuthorDemo.id,
+        "posts",
+        validate=validate,
+    )
+
+    assert [author.name for author in result] == ["John", "Jane", "Jim"]
+    assert [post.name for post in result[0].posts] == ["First", "Second"]
+    assert result[1].posts == []
+    assert result[2].posts == [PostDemo(id=12, name="Third", user_id=3)]
+
+
+def test_grouped_requires_ordered_input():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo)
+    values = build_author_rows(
+        (1, "John", 10, "First"),
+        (2, "Jane", 11, "Second"),
+        (1, "John", 12, "Third"),
+    )
+
+    result = optimize_exec_grouped(
+        values, select_raw, select_types, AuthorDemo.id, "posts"
+    )
+
+    # Groups are only formed from consecutive rows
+    assert [author.id for author in result] == [1, 2, 1]
+
+
+def test_grouped_dict_output():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo)
+    values = build_author_rows((1, "John", 10, "First"))
+
+    result = optimize_exec_grouped(
+        values,
+        select_raw,
+        select_types,
+        AuthorDemo.id,
+        "posts",
+        output_mode="dict",
+    )
+
+    assert result == [
+        {
+            "id": 1,
+            "name": "John",
+            "posts": [{"id": 10, "name": "First", "user_id": 1}],
+        }
+    ]
+
+
+def test_grouped_rejects_unrelated_group_by():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo)
+
+    with pytest.raises(ValueError, match="group_by"):
+        optimize_exec_grouped([], select_raw, select_types, UserDemo.id, "posts")
+
+
+def build_tenant_rows(*rows: tuple[int, int, int]) -> list[dict[str, Any]]:
+    return [
+        {
+            "tenantdemo_tenant_id": tenant_id,
+            "tenantdemo_id": tenant_row_id,
+            "tenantdemo_name": f"{tenant_id}-{tenant_row_id}",
+            "postdemo_id": post_id,
+            "postdemo_name": f"Post {post_id}",
+            "postdemo_user_id": tenant_row_id,
+        }
+        for tenant_id, tenant_row_id, post_id in rows
+    ]
+
+
+def test_grouped_by_composite_primary_key():
+    select_raw, select_types = build_selects(TenantDemo, PostDemo)
+    # Consecutive rows share either the tenant or the id, but never both
+    values = build_tenant_rows(
+        (1, 1, 10), (1, 1, 11), (1, 2, 12), (2, 2, 13), (2, 1, 14)
+    )
+
+    result = optimize_exec_grouped(
+        values, select_raw, select_types, TenantDemo, "members"
+    )
+
+    assert [tenant.name for tenant in result] == ["1-1", "1-2", "2-2", "2-1"]
+    assert [[post.id for post in tenant.members] for tenant in result] == [
+        [10, 11],
+        [12],
+        [13],
+        [14],
+    ]
+
+    # Naming the columns explicitly groups the same way
+    assert optimize_exec_grouped(
+        values,
+        select_raw,
+        select_types,
+        (TenantDemo.tenant_id, TenantDemo.id),
+        "members",
+    ) == result
+
+
+def test_dedup_by_composite_primary_key():
+    select_raw, select_types = build_selects(TenantDemo)
+    values = [
+        {
+            "tenantdemo_tenant_id": tenant_id,
+            "tenantdemo_id": row_id,
+            "tenantdemo_name": f"{tenant_id}-{row_id}",
+        }
+        for tenant_id, row_id in [(1, 1), (1, 2), (2, 1), (1, 1)]
+    ]
+
+    result = optimize_exec_casting(values, select_raw, select_types, dedup_by_pk=True)
+
+    assert result[0] is result[3]
+    assert len({id(tenant) for tenant in result}) == 3
+
+
+def test_grouped_rejects_column_selects():
+    select_raw, select_types = build_selects(AuthorDemo, PostDemo.name)
+
+    with pytest.raises(ValueError, match="parent and a child table"):
+        optimize_exec_grouped([], select_raw, select_types, AuthorDemo.id, "posts")
+
+
+#
+# Lenient casting
+#
+
+
+def test_lenient_collects_row_errors():
+    select_raw, select_types = build_selects(UserDemo, EventDemo.created_at)
+    values = [
+        {
+            "userdemo_id": 1,
+            "userdemo_name": "John",
+            "userdemo_email": "john@example.com",
+            "eventdemo_created_at": "2024-01-01T00:00:00",
+        },
+        {
+            "userdemo_id": 2,
+            "userdemo_name": "Jane",
+            "eventdemo_created_at": "2024-01-02T00:00:00",
+        },
+        {
+            "userdemo_id": "not-an-int",
+            "userdemo_name": "Jim",
+            "userdemo_email": "jim@example.com",
+            "eventdemo_created_at": "2024-01-03T00:00:00",
+        },
+        {
+            "userdemo_id": 4,
+            "userdemo_name": "Jill",
+            "userdemo_email": "jill@example.com",
+            "eventdemo_created_at": "yesterday",
+        },
+        {
+            "userdemo_id": 5,
+            "userdemo_name": "Jack",
+            "userdemo_email": "jack@example.com",
+            "eventdemo_created_at": "2024-01-05T00:00:00",
+        },
+    ]
+
+    results, errors = optimize_exec_lenient(values, select_raw, select_types)
+
+    assert [(user.id, created_at.day) for user, created_at in results] == [
+        (1, 1),
+        (5, 5),
+    ]
+    assert [row_index for row_index, _ in errors] == [1, 2, 3]
+    assert errors[0][1] == (
+        "Key 'userdemo_email' for field 'email' not found in row 1 (select index 0)."
+    )
+    assert "id" in errors[1][1]
+    assert errors[2][1].startswith("Invalid value for 'created_at' in row 3")
+
+
+def test_lenient_skips_malformed_rows():
+    select_raw, select_types = build_selects(UserDemo.id)
+    values = [{"userdemo_id": 0}, None, {"userdemo_id": 2}]
+
+    results, errors = optimize_exec_lenient(values, select_raw, select_types)
+
+    assert results == [0, 2]
+    assert errors == [(1, "Row 1 is not a mapping (got NoneType)")]
+
+
+def test_lenient_without_errors():
+    select_raw, select_types = build_selects(UserDemo.id)
+    values = [{"userdemo_id": i} for i in range(3)]
+
+    assert optimize_exec_lenient(values, select_raw, select_types) == ([0, 1, 2], [])
+
+
+#
+# Prepared selects
+#
+
+
+def test_prepared_select_materializes_batches():
+    select_raw, select_types = build_selects(UserDemo, EventDemo.created_at)
+    prepared = PreparedSelect(select_raw, select_types)
+
+    for page in range(3):
+        values = [
+            {
+                "userdemo_id": page * 10 + i,
+                "userdemo_name": f"User {i}",
+                "userdemo_email": f"user{i}@example.com",
+                "eventdemo_created_at": f"2024-01-0{page + 1}T00:00:00",
+            }
+            for i in range(2)
+        ]
+
+        result = prepared.materialize(values)
+
+        assert result == optimize_exec_casting(values, select_raw, select_types)
+        assert [user.id for user, _ in result] == [page * 10, page * 10 + 1]
+        assert all(created_at.day == page + 1 for _, created_at in result)
+
+
+@pytest.mark.parametrize("reuse_buffers", [True, False])
+@pytest.mark.parametrize("output_mode", ["model", "namedtuple"])
+def test_prepared_select_rows_are_independent(output_mode: str, reuse_buffers: bool):
+    select_raw, select_types = build_selects(JsonDemo)
+    prepared = PreparedSelect(
+        select_raw, select_types, output_mode=output_mode, reuse_buffers=reuse_buffers
+    )
+    values = [
+        {
+            "jsondemo_id": i,
+            "jsondemo_settings": f'{{"index": {i}}}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": f"value-{i}",
+        }
+        for i in range(3)
+    ]
+
+    first = prepared.materialize(values[:2])
+    second = prepared.materialize(values[2:])
+
+    # Rows are built from a shared buffer, which must never leak into the results
+    assert [row.id for row in first + second] == [0, 1, 2]
+    assert [row.settings for row in first + second] == [
+        {"index": 0},
+        {"index": 1},
+        {"index": 2},
+    ]
+
+
+def test_prepared_select_keeps_options():
+    select_raw, select_types = build_selects(UserDemo)
+    prepared = PreparedSelect(select_raw, select_types, output_mode="dict")
+
+    assert prepared.materialize([]) == []
+    assert prepared.materialize(
+        [{"userdemo_id": 1, "userdemo_name": "John", "userdemo_email": "j@example.com"}]
+    ) == [{"id": 1, "name": "John", "email": "j@example.com"}]
+
+    with pytest.raises(ValueError, match="output_mode"):
+        PreparedSelect(select_raw, select_types, output_mode="rows")
+
+
+def test_prepared_select_warns_on_every_batch():
+    select_raw, select_types = build_selects(ContactDemo)
+    prepared = PreparedSelect(
+        select_raw, select_types, strict=False, output_mode="dict", warn_on_missing=True
+    )
+
+    for batch in range(2):
+        with catch_warnings(record=True) as caught:
+            simplefilter("always")
+            prepared.materialize([{"contactdemo_id": batch, "contactdemo_name": "Jo"}])
+
+        assert [str(warning.message) for warning in caught] == [
+            "Key 'contactdemo_email' for 'email' not found in row 0 (select index 0)."
+        ]
+
+
+def test_prepared_select_reports_per_batch():
+    select_raw, select_types = build_selects(JsonDemo)
+    prepared = PreparedSelect(
+        select_raw, select_types, collect_timings=True, track_provenance=True
+    )
+    values = [
+        {
+            "jsondemo_id": 1,
+            "jsondemo_settings": '{"theme": "dark"}',
+            "jsondemo_metadata": None,
+            "jsondemo_unique_val": "value",
+        }
+    ]
+
+    first = prepared.materialize(values)
+    result, timings, provenance = prepared.materialize(iter(values))
+
+    assert result == first[0] == optimize_exec_casting(values, select_raw, select_types)
+    # Planning happened up front, so batches only time their own casting
+    assert timings["plan_build"] == first[1]["plan_build"] == 0
+    assert timings["json_parse"] > 0
+    assert provenance == [
+        (
+            {
+                "id": "present",
+                "settings": "present",
+                "metadata": "null",
+                "unique_val": "present",
+            },
+        )
+    ]
@@ -12,6 +12,15 @@ class Alias(Generic[T]):
         return self.name
 
 
+class TableAlias(Generic[T]):
+    def __init__(self, name: str, table: type[T]):
+        self.name = name
+        self.table = table
+
+    def __str__(self):
+        return self.name
+
+
 def alias(name: str, type: T) -> T:
     """
     Creates an alias for a field in raw SQL queries, allowing for type-safe mapping of raw SQL results.
@@ -54,3 +63,33 @@ def alias(name: str, type: T) -> T:
 
     """
     return cast(T, Alias(name, type))
+
+
+def alias_table(name: str, table: type[T]) -> type[T]:
+    """
+    Names one instance of a table in raw SQL queries, so the same model can be selected more
+    than once, like both sides of a self-join. Each instance's fields are read from row keys
+    prefixed with `{name}_` instead of the table name, and the instance is keyed by `name` in
+    dict output.
+
+    ```python {{sticky: True}}
+    manager = alias_table("manager", Employee)
+    report = alias_table("report", Employee)
+
+    select((manager, report)).text(
+        '''
+        SELECT
+            m.id AS manager_id, m.name AS manager_name,
+            r.id AS report_id, r.name AS report_name
+        FROM employee m
+        JOIN employee r ON r.manager_id = m.id
+        '''
+    )
+    ```
+
+    :param name: The instance alias, used as the row key prefix of its columns
+    :param table: The table class to hydrate from those columns
+    :return: A table alias that can be used in select() statements
+
+    """
+    return cast(type[T], TableAlias(name, table))
@@ -37,6 +37,16 @@ class DBFieldInputs(_FieldInfoInputs, total=False):
     index: bool
     check_expression: str | None
     is_json: bool
+    is_msgpack: bool
+    json_path: str | None
+    double_decode: bool
+    accept_scalar: bool
+    coercions: list[str] | None
+    default_json: Any
+    codec: str | None
+    epoch_unit: str | None
+    is_money: bool
+    is_bit: bool
 
 
 class DBFieldInfo(FieldInfo):
@@ -97,6 +107,90 @@ class DBFieldInfo(FieldInfo):
     When True, the field's value will be JSON serialized before storage.
     """
 
+    is_msgpack: bool = False
+    """
+    Indicates if this field is stored as a msgpack blob. When True, the optimized
+    casting decodes the column's bytes with the `unpackb` callable passed to it, and
+    then treats the decoded value like a parsed JSON field. Only applies when reading
+    results back.
+    """
+
+    json_path: str | None = None
+    """
+    Dotted path into a JSON column, like "user.id", for fields that only need one
+    nested value out of a larger payload. The optimized casting reads the value at
+    this path after parsing, or None when any part of the path is absent. Only
+    applies when reading results back.
+    """
+
+    double_decode: bool = False
+    """
+    Indicates if this JSON column holds double-encoded payloads, where the stored JSON
+    value is a string of more JSON text. The optimized casting parses a string that
+    starts like an object or array a second time, and leaves it as a string when that
+    fails. Only applies when reading results back.
+    """
+
+    accept_scalar: bool = False
+    """
+    Indicates if this JSON column, annotated as a list of a type like UUID or Decimal,
+    can also hold a single value instead of an array. The optimized casting converts
+    that value to the element type and keeps it as-is, where it would otherwise reject
+    it. Only applies when reading results back.
+    """
+
+    coercions: list[str] | None = None
+    """
+    Ordered steps that the optimized casting applies to this JSON column's values, for
+    payloads that don't fit the default of decoding, extracting the `json_path`, and
+    then building the submodel or converting the value. Steps are "decode" to parse JSON
+    text (or unpack msgpack, as the first step), "path" to extract the `json_path`,
+    "codec" to convert the value or its array elements to the annotated type, and
+    "submodel" to build the annotated pydantic model. Steps can repeat, like
+    `["decode", "path", "decode", "submodel"]` for JSON text nested inside the payload.
+    Only applies when reading results back.
+    """
+
+    default_json: Any = None
+    """
+    Value for a JSON field to take in place of None, like `{}` or `[]`, when its column
+    is missing from the results, NULL, or holds a JSON null. The optimized casting hands
+    each row its own copy, so mutable defaults aren't shared.
+    """
+
+    codec: str | None = None
+    """
+    Tag of the decoder that should convert this column's values when they're read back,
+    like "inet". Decoders are registered per query through the `codecs` option of the
+    optimized casting, and values pass through unchanged when no decoder is registered.
+    """
+
+    epoch_unit: str | None = None
+    """
+    Unit of the Unix epoch numbers that a datetime column stores in place of timestamps,
+    either "seconds" or "milliseconds". The optimized casting converts integer and float
+    values to UTC datetimes with `datetime.fromtimestamp`, while ISO strings are still
+    parsed as usual. Only applies when reading results back.
+    """
+
+    is_money: bool = False
+    """
+    Indicates if this Decimal column is a Postgres `money` value, which drivers return
+    as formatted text like "$1,234.56" or "($5.00)". The optimized casting strips the
+    currency symbol and thousands separators, and reads parentheses as a negative sign.
+    The symbols and separators follow the `money_symbols` and `money_separators` options
+    of the optimized casting. Only applies when reading results back.
+    """
+
+    is_bit: bool = False
+    """
+    Indicates if this column is a Postgres `bit(n)` or `varbit` value, which drivers
+    return as strings of 0s and 1s. The optimized casting validates the digits and
+    converts them according to the field's annotation: `str` keeps the bit string,
+    `int` reads it as a binary number, and `bytes` packs the bits left-aligned, the
+    way Postgres stores them. Only applies when reading results back.
+    """
+
     def __init__(self, **kwargs: Unpack[DBFieldInputs]):
         """
         Initialize a new DBFieldInfo instance with the given field configuration.
@@ -119,6 +213,16 @@ class DBFieldInfo(FieldInfo):
         self.index = kwargs.pop("index", False)
         self.check_expression = kwargs.pop("check_expression", None)
         self.is_json = kwargs.pop("is_json", False)
+        self.is_msgpack = kwargs.pop("is_msgpack", False)
+        self.json_path = kwargs.pop("json_path", None)
+        self.double_decode = kwargs.pop("double_decode", False)
+        self.accept_scalar = kwargs.pop("accept_scalar", False)
+        self.coercions = kwargs.pop("coercions", None)
+        self.default_json = kwargs.pop("default_json", None)
+        self.codec = kwargs.pop("codec", None)
+        self.epoch_unit = kwargs.pop("epoch_unit", None)
+        self.is_money = kwargs.pop("is_money", False)
+        self.is_bit = kwargs.pop("is_bit", False)
 
     @classmethod
     def extend_field(
@@ -131,6 +235,16 @@ class DBFieldInfo(FieldInfo):
         index: bool,
         check_expression: str | None,
         is_json: bool,
+        codec: str | None = None,
+        json_path: str | None = None,
+        is_msgpack: bool = False,
+        default_json: Any = None,
+        epoch_unit: str | None = None,
+        double_decode: bool = False,
+        is_money: bool = False,
+        coercions: list[str] | None = None,
+        is_bit: bool = False,
+        accept_scalar: bool = False,
     ):
         """
         Helper function to extend a Pydantic FieldInfo with database-specific attributes.
@@ -144,6 +258,16 @@ class DBFieldInfo(FieldInfo):
             index=index,
             check_expression=check_expression,
             is_json=is_json,
+            is_msgpack=is_msgpack,
+            json_path=json_path,
+            default_json=default_json,
+            codec=codec,
+            epoch_unit=epoch_unit,
+            double_decode=double_decode,
+            is_money=is_money,
+            coercions=coercions,
+            is_bit=is_bit,
+            accept_scalar=accept_scalar,
             **field._attributes_set,  # type: ignore
         )
 
@@ -168,6 +292,16 @@ def __get_db_field(_: Callable[Concatenate[Any, P], Any] = PydanticField):  # ty
         index: bool = False,
         check_expression: str | None = None,
         is_json: bool = False,
+        is_msgpack: bool = False,
+        json_path: str | None = None,
+        double_decode: bool = False,
+        accept_scalar: bool = False,
+        coercions: list[str] | None = None,
+        default_json: Any = None,
+        codec: str | None = None,
+        epoch_unit: str | None = None,
+        is_money: bool = False,
+        is_bit: bool = False,
         default: Any = _Unset,
         default_factory: (
             Callable[[], Any] | Callable[[dict[str, Any]], Any] | None
@@ -192,6 +326,16 @@ def __get_db_field(_: Callable[Concatenate[Any, P], Any] = PydanticField):  # ty
                 index=index,
                 check_expression=check_expression,
                 is_json=is_json,
+                is_msgpack=is_msgpack,
+                json_path=json_path,
+                double_decode=double_decode,
+                accept_scalar=accept_scalar,
+                coercions=coercions,
+                default_json=default_json,
+                codec=codec,
+                epoch_unit=epoch_unit,
+                is_money=is_money,
+                is_bit=is_bit,
             ),
         )
 
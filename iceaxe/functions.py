@@ -86,15 +86,24 @@ class FunctionMetadata(ComparisonBase):
     Optional alias for the function result in the query
     """
 
+    output_alias: str | None = None
+    """
+    Optional name the executed SQL gave the result instead of `local_name`, like `total` in
+    `COUNT(*) AS total`, set through `func.as_`. Rows are read from this key first, falling
+    back to `local_name`.
+    """
+
     def __init__(
         self,
         literal: QueryLiteral,
         original_field: DBFieldClassDefinition,
         local_name: str | None = None,
+        output_alias: str | None = None,
     ):
         self.literal = literal
         self.original_field = original_field
         self.local_name = local_name
+        self.output_alias = output_alias
 
     def to_query(self):
         """
@@ -579,6 +588,25 @@ class FunctionBuilder:
         metadata.literal = QueryLiteral(f"to_timestamp({metadata.literal}, '{format}')")
         return cast(datetime, metadata)
 
+    def as_(self, field: T, output_alias: str) -> T:
+        """
+        Reads the function's result from `output_alias` in each row, for SQL that aliases
+        the expression itself. The alias carries over when the result is wrapped in further
+        function calls, and rows without it still resolve through the generated local name.
+
+        :param field: The function call whose result is aliased
+        :param output_alias: The name the executed SQL gives the result
+        :return: A function metadata object preserving the input type
+
+        ```python {{sticky: True}}
+        # Rows from SQL like `SELECT COUNT(*) AS total`
+        total = func.as_(func.count(User.id), "total")
+        ```
+        """
+        metadata = self._column_to_metadata(field)
+        metadata.output_alias = output_alias
+        return cast(T, metadata)
+
     def _column_to_metadata(self, field: Any) -> FunctionMetadata:
         """
         Internal helper method to convert a field to FunctionMetadata.
@@ -30,6 +30,7 @@ from iceaxe.typing import (
     is_comparison,
     is_comparison_group,
     is_function_metadata,
+    is_table_alias,
 )
 
 P = TypeVar("P")
@@ -428,6 +429,7 @@ class QueryBuilder(Generic[P, QueryType]):
                 not is_column(field)
                 and not is_base_table(field)
                 and not is_alias(field)
+                and not is_table_alias(field)
                 and not is_function_metadata(field)
             ):
                 raise ValueError(
@@ -461,7 +463,7 @@ class QueryBuilder(Generic[P, QueryType]):
             if is_column(field) or is_base_table(field):
                 self._select_fields.append(sql.select(field))
                 self._select_raw.append(field)
-            elif is_alias(field):
+            elif is_alias(field) or is_table_alias(field):
                 # We don't actually add the alias to the selection query, assuming
                 # that it's captured in the raw query.
                 self._select_raw.append(field)
@@ -1,212 +1,3847 @@
-from typing import Any, List, Tuple
+from cpython.exc cimport PyErr_CheckSignals
+from datetime import date, datetime, time, timedelta, timezone
+from decimal import Decimal, InvalidOperation
+from enum import Enum
+from functools import partial
+from collections import OrderedDict, namedtuple
+from copy import deepcopy
+from inspect import isclass
+from itertools import islice
+from pathlib import PurePath
+from time import perf_counter_ns
+from types import NoneType, UnionType
+from typing import (
+    Annotated,
+    Any,
+    Dict,
+    Iterable,
+    Iterator,
+    List,
+    NotRequired,
+    Required,
+    Tuple,
+    Union,
+    get_args,
+    get_origin,
+    get_type_hints,
+    is_typeddict,
+)
+from uuid import UUID
+from warnings import warn
+from pydantic import BaseModel
+from pydantic_core import PydanticUndefined, to_jsonable_python
 from iceaxe.base import TableBase
+from iceaxe.field import DBFieldInfo
 from iceaxe.queries import FunctionMetadata
-from iceaxe.alias_values import Alias
-from json import loads as json_loads
-from cpython.ref cimport PyObject
-from cpython.object cimport PyObject_GetItem
-from libc.stdlib cimport malloc, free
-from libc.string cimport memcpy
-from cpython.ref cimport Py_INCREF, Py_DECREF
-
-cdef struct FieldInfo:
-    char* name                # Field name
-    char* select_attribute    # Corresponding attribute in the select_raw
-    bint is_json              # Flag indicating if the field is JSON
-
-cdef char* allocate_cstring(bytes data):
-    cdef Py_ssize_t length = len(data)
-    cdef char* c_str = <char*>malloc((length + 1) * sizeof(char))
-    if not c_str:
-        raise MemoryError("Failed to allocate memory for C string.")
-    memcpy(c_str, <char*>data, length)  # Cast bytes to char* for memcpy
-    c_str[length] = 0  # Null-terminate the string
-    return c_str
-
-cdef void free_fields(FieldInfo** fields, Py_ssize_t* num_fields_array, Py_ssize_t num_selects):
-    cdef Py_ssize_t j, k
-    if fields:
-        for j in range(num_selects):
-            if fields[j]:
-                for k in range(num_fields_array[j]):
-                    free(fields[j][k].name)
-                    free(fields[j][k].select_attribute)
-                free(fields[j])
-        free(fields)
-    if num_fields_array:
-        free(num_fields_array)
-
-cdef FieldInfo** precompute_fields(list select_raws, list select_types, Py_ssize_t num_selects, Py_ssize_t* num_fields_array):
-    cdef FieldInfo** fields = <FieldInfo**>malloc(num_selects * sizeof(FieldInfo*))
-    cdef Py_ssize_t j, k, num_fields
-    cdef dict field_dict
-    cdef bytes select_bytes, field_bytes
-    cdef char* c_select
-    cdef char* c_field
+from iceaxe.alias_values import Alias, TableAlias
+from iceaxe.typing import is_base_table, is_column, is_function_metadata
+from json import dumps as stdlib_json_dumps, loads as stdlib_json_loads
+
+class NoRowsError(LookupError):
+    """
+    Raised by `optimize_exec_one` when the query didn't return any rows.
+
+    """
+
+class MultipleRowsError(LookupError):
+    """
+    Raised by `optimize_exec_one` when the query returned more than one row.
+
+    """
+
+class MissingColumnWarning(UserWarning):
+    """
+    Emitted when casting with `strict=False` and `warn_on_missing=True` for each selected key
+    that's missing from the results.
+
+    """
+
+class FrozenModelError(AttributeError):
+    """
+    Raised when setting a field on an object that was cast with `freeze=True`.
+
+    """
+
+# Returned by an `on_missing` callback to leave the value to the usual lenient handling
+SKIP_MISSING = object()
+
+class NonFiniteJSONError(ValueError):
+    """
+    Raised for `NaN`, `Infinity`, or `-Infinity` in a JSON payload when casting with
+    `json_strict=True`.
+
+    """
+
+def reject_json_constant(str constant):
+    raise NonFiniteJSONError(f"Non-finite JSON value {constant} isn't allowed with json_strict")
+
+cdef enum SelectKind:
+    SELECT_TABLE
+    SELECT_COLUMN
+    SELECT_FUNCTION
+    SELECT_ALIAS
+    SELECT_UNKNOWN
+
+# Describes one entry of the select for `optimize_exec_typed`
+SelectSchema = namedtuple("SelectSchema", ["name", "kind", "type"])
+
+cdef dict SELECT_KIND_NAMES = {
+    SELECT_TABLE: "table",
+    SELECT_COLUMN: "column",
+    SELECT_FUNCTION: "function",
+    SELECT_ALIAS: "alias",
+    SELECT_UNKNOWN: "unknown",
+}
+
+cdef enum Codec:
+    CODEC_NONE
+    CODEC_DATETIME
+    CODEC_DATE
+    CODEC_TIME
+    CODEC_INTERVAL
+    CODEC_UUID
+    CODEC_DECIMAL
+    CODEC_INT
+    CODEC_FLOAT
+    CODEC_BOOL
+    CODEC_ENUM
+    CODEC_ARRAY
+    CODEC_CUSTOM
+    CODEC_BYTES
+    CODEC_MEMORYVIEW
+    CODEC_PATH
+    CODEC_EPOCH_SECONDS
+    CODEC_EPOCH_MILLIS
+    CODEC_MONEY
+    CODEC_BIT_STRING
+    CODEC_BIT_INT
+    CODEC_BIT_BYTES
+
+# Conversions as `optimize_exec_explain` reports them
+cdef dict CODEC_NAMES = {
+    CODEC_DATETIME: "datetime",
+    CODEC_DATE: "date",
+    CODEC_TIME: "time",
+    CODEC_INTERVAL: "interval",
+    CODEC_UUID: "uuid",
+    CODEC_DECIMAL: "decimal",
+    CODEC_INT: "int",
+    CODEC_FLOAT: "float",
+    CODEC_BOOL: "bool",
+    CODEC_ENUM: "enum",
+    CODEC_ARRAY: "array",
+    CODEC_CUSTOM: "custom",
+    CODEC_BYTES: "bytes",
+    CODEC_MEMORYVIEW: "memoryview",
+    CODEC_PATH: "path",
+    CODEC_EPOCH_SECONDS: "epoch_seconds",
+    CODEC_EPOCH_MILLIS: "epoch_milliseconds",
+    CODEC_MONEY: "money",
+    CODEC_BIT_STRING: "bit_string",
+    CODEC_BIT_INT: "bit_int",
+    CODEC_BIT_BYTES: "bit_bytes",
+}
+
+cdef enum Coercion:
+    COERCE_DECODE
+    COERCE_PATH
+    COERCE_CODEC
+    COERCE_SUBMODEL
+
+cdef dict COERCIONS = {
+    "decode": COERCE_DECODE,
+    "path": COERCE_PATH,
+    "codec": COERCE_CODEC,
+    "submodel": COERCE_SUBMODEL,
+}
+
+cdef dict COERCION_NAMES = {step: name for name, step in COERCIONS.items()}
+
+cdef dict EPOCH_CODECS = {
+    "seconds": CODEC_EPOCH_SECONDS,
+    "milliseconds": CODEC_EPOCH_MILLIS,
+}
+
+cdef class FieldPlan:
+    """
+    Lookup details for one model field, or for the single value of a column, function, or
+    alias select. Built once per query and shared by every row, so the row loop only reads
+    these slots and never goes back to the pydantic `FieldInfo`.
+
+    """
+    cdef str name                # Field name
+    cdef str select_attribute    # Corresponding key in the result row
+    cdef tuple fallback_attributes  # Keys to try in order when select_attribute is missing
+    cdef str init_name           # Keyword the validating constructor expects, which may be an alias
+    cdef str output_key          # Key of the value in dict and columnar output
+    cdef Py_ssize_t column_index # Position of the value when rows are positional tuples
+    cdef Py_ssize_t select_index # Position of the owning select, reported in errors
+    cdef object transform        # Caller-provided callable applied to every fetched value
+    cdef bint non_null           # Fail the row when its value is a SQL NULL
+    cdef bint optional_key       # Leave the field out when rows lack its key, even if strict
+    cdef bint is_json            # Flag indicating if the field is JSON
+    cdef bint is_msgpack         # JSON-like field stored as a msgpack blob instead of text
+    cdef tuple json_path         # Keys to follow into the parsed JSON payload, if any
+    cdef bint double_decode      # Parse JSON text that decodes into more JSON text once more
+    cdef bint accept_scalar      # Convert a JSON list field's non-array value as one element
+    cdef tuple coercions         # Coercion steps applied in order to non-null JSON values
+    cdef object default_json     # Value to copy in place of a missing or null JSON value
+    cdef list batch_values       # Payloads parsed ahead of the row loop, indexed by row
+    cdef str codec_tag           # Registered decoder to use for this field, if any
+    cdef Codec codec             # Conversion for non-null values that the driver leaves as text
+    cdef Codec element_codec     # Conversion for the leaves of CODEC_ARRAY values
+    cdef object target_type      # Enum class for CODEC_ENUM values (or array leaves), the
+                                 # registered decoder for CODEC_CUSTOM, or the separators of
+                                 # numeric and money text
+    cdef object submodel         # Pydantic model to build from a parsed JSON object, if any
+    cdef bint submodel_many      # The JSON payload is an array of submodel objects
+    cdef object default          # Declared default, or PydanticUndefined
+    cdef object default_factory  # Declared default factory, called once per missing value
+
+    def __init__(
+        self,
+        str name,
+        str select_attribute,
+        bint is_json,
+        object annotation,
+        tuple fallback_attributes=(),
+        str init_name=None,
+        object default=PydanticUndefined,
+        object default_factory=None,
+        str codec_tag=None,
+        str json_path=None,
+    ):
+        self.name = name
+        self.select_attribute = select_attribute
+        self.fallback_attributes = fallback_attributes
+        self.init_name = name if init_name is None else init_name
+        self.output_key = self.name
+        self.column_index = -1
+        self.select_index = -1
+        self.transform = None
+        self.non_null = False
+        self.optional_key = False
+        self.default = default
+        self.default_factory = default_factory
+        self.is_json = is_json
+        self.is_msgpack = False
+        self.default_json = None
+        self.batch_values = None
+        self.json_path = None if json_path is None else tuple(json_path.split("."))
+        self.double_decode = False
+        self.accept_scalar = False
+        self.coercions = ()
+        self.codec_tag = codec_tag
+        self.codec = CODEC_NONE
+        self.element_codec = CODEC_NONE
+        self.target_type = None
+        self.submodel = None
+        self.submodel_many = False
+
+        annotation = unwrap_optional(annotation)
+
+        # JSON payloads are decoded wholesale, so their annotation only matters when it names
+        # a submodel that the parsed objects should be hydrated into, or a scalar type (or
+        # list of one) that JSON has no native representation for, like UUID or Decimal
+        if is_json:
+            if get_origin(annotation) is list and get_args(annotation):
+                self.submodel = resolve_submodel(unwrap_optional(get_args(annotation)[0]))
+                self.submodel_many = self.submodel is not None
+                if self.submodel is None:
+                    self.target_type = array_leaf_type(annotation)
+                    self.element_codec = resolve_element_codec(self.target_type)
+                    if self.element_codec != CODEC_NONE:
+                        self.codec = CODEC_ARRAY
+            else:
+                self.submodel = resolve_submodel(annotation)
+                if self.submodel is None:
+                    self.codec = resolve_codec(annotation)
+                    self.target_type = annotation
+            return
+
+        if get_origin(annotation) is list:
+            self.codec = CODEC_ARRAY
+            self.target_type = array_leaf_type(annotation)
+            self.element_codec = resolve_element_codec(self.target_type)
+        else:
+            self.codec = resolve_codec(annotation)
+            self.target_type = annotation
+
+    cdef FieldPlan copy(self, Py_ssize_t offset):
+        """
+        Clone a cached plan for one query, with its column moved `offset` positions along.
+        Every slot is copied, so per-query settings like transforms never leak back into
+        the cache.
+
+        """
+        cdef FieldPlan plan = FieldPlan.__new__(FieldPlan)
+        plan.name = self.name
+        plan.select_attribute = self.select_attribute
+        plan.fallback_attributes = self.fallback_attributes
+        plan.init_name = self.init_name
+        plan.output_key = self.output_key
+        plan.column_index = self.column_index + offset
+        plan.select_index = self.select_index
+        plan.transform = self.transform
+        plan.non_null = self.non_null
+        plan.optional_key = self.optional_key
+        plan.is_json = self.is_json
+        plan.is_msgpack = self.is_msgpack
+        plan.json_path = self.json_path
+        plan.double_decode = self.double_decode
+        plan.accept_scalar = self.accept_scalar
+        plan.coercions = self.coercions
+        plan.default_json = self.default_json
+        plan.batch_values = self.batch_values
+        plan.codec_tag = self.codec_tag
+        plan.codec = self.codec
+        plan.element_codec = self.element_codec
+        plan.target_type = self.target_type
+        plan.submodel = self.submodel
+        plan.submodel_many = self.submodel_many
+        plan.default = self.default
+        plan.default_factory = self.default_factory
+        return plan
+
+cdef class LazyModel:
+    """
+    Stand-in for a table object that defers building it, returned by the `lazy` casting
+    option. The first attribute read builds the model from the field values that were cast
+    for it, and every read after that goes to the same instance. Only reads and comparisons
+    are forwarded, so `isinstance` checks and attribute assignments see the proxy itself.
+
+    """
+    cdef object _model
+    cdef dict _fields
+    cdef bint _validate
+    cdef bint _freeze
+    cdef object _instance
+
+    def __init__(self, object model, dict fields, bint validate, bint freeze=False):
+        self._model = model
+        self._fields = fields
+        self._validate = validate
+        self._freeze = freeze
+        self._instance = None
+
+    cdef object resolve(self):
+        if self._instance is None:
+            if self._validate:
+                self._instance = self._model(**self._fields)
+            else:
+                self._instance = construct_model(self._model, self._fields)
+            if self._freeze:
+                freeze_instance(self._instance)
+            # The field values now live on the instance
+            self._fields = None
+        return self._instance
+
+    def __getattr__(self, str name):
+        return getattr(self.resolve(), name)
+
+    def __eq__(self, object other):
+        if isinstance(other, LazyModel):
+            other = (<LazyModel>other).resolve()
+        return self.resolve() == other
+
+    def __repr__(self):
+        if self._instance is None:
+            return f"<unbuilt {self._model.__name__}>"
+        return repr(self._instance)
+
+cdef class SelectPlan:
+    """
+    Everything we need to know about one entry of the select, resolved ahead of the row loop
+    so we don't repeat the same attribute lookups and string formatting for every row.
+
+    """
+    cdef object select_raw
+    cdef SelectKind kind
+    cdef str output_name         # Key used when the row is emitted as a dict
+    cdef FieldPlan column        # Row lookup for column, function, and alias selects
+    cdef list fields             # FieldPlan entries for table selects
+    cdef object row_type         # namedtuple for table selects, created on first use
+    cdef str extra_prefix        # Row key prefix of unmapped columns passed through as extras
+    cdef str key_prefix          # This table's own row key prefix, stripped from extra keys
+    cdef frozenset mapped_keys   # Row keys consumed by any select, never treated as extras
+    cdef list pk_fields          # FieldPlan entries of the primary key, when deduplicating
+    cdef dict instances          # Objects already built for this select, by primary key
+    cdef dict kwargs_buffer      # Field values dict reused across rows, when that's safe
+    cdef bint freeze             # Built objects need to reject field assignments
+
+    def __init__(
+        self,
+        object select_raw,
+        SelectKind kind,
+        str output_name,
+        FieldPlan column,
+        list fields,
+    ):
+        self.select_raw = select_raw
+        self.kind = kind
+        self.output_name = output_name
+        self.column = column
+        self.fields = fields
+        self.row_type = None
+        self.extra_prefix = None
+        self.key_prefix = None
+        self.mapped_keys = None
+        self.pk_fields = None
+        self.instances = None
+        self.kwargs_buffer = None
+        self.freeze = False
+
+cdef object unwrap_optional(object annotation):
+    cdef list args
+    if get_origin(annotation) in (Union, UnionType):
+        args = [arg for arg in get_args(annotation) if arg is not NoneType]
+        if len(args) == 1:
+            return args[0]
+    return annotation
+
+cdef Codec resolve_codec(object annotation):
+    annotation = unwrap_optional(annotation)
+    if not isclass(annotation):
+        return CODEC_NONE
+    # Checked first, since str and int enums would otherwise pass as their primitive
+    if issubclass(annotation, Enum):
+        return CODEC_ENUM
+    if annotation is bool:
+        return CODEC_BOOL
+    if annotation is float:
+        return CODEC_FLOAT
+    if issubclass(annotation, datetime):
+        return CODEC_DATETIME
+    # datetime subclasses date, so this has to come after the datetime check
+    if issubclass(annotation, date):
+        return CODEC_DATE
+    if issubclass(annotation, time):
+        return CODEC_TIME
+    if issubclass(annotation, timedelta):
+        return CODEC_INTERVAL
+    if issubclass(annotation, UUID):
+        return CODEC_UUID
+    if issubclass(annotation, Decimal):
+        return CODEC_DECIMAL
+    if issubclass(annotation, PurePath):
+        return CODEC_PATH
+    return CODEC_NONE
+
+cdef Codec resolve_element_codec(object annotation):
+    # Integer columns already arrive as ints, but the elements of an array literal are still text
+    if annotation is int:
+        return CODEC_INT
+    return resolve_codec(annotation)
+
+cdef object resolve_submodel(object annotation):
+    if isclass(annotation) and (issubclass(annotation, BaseModel) or is_legacy_model(annotation)):
+        return annotation
+    return None
+
+class LegacyFieldInfo:
+    """
+    A pydantic v1 `ModelField` exposed through the `FieldInfo` attributes that planning reads,
+    so v1 models go through the same plans as v2 ones. Options that v1 doesn't know about,
+    like `is_json` or `primary_key`, end up in the field's `extra` dict.
+
+    """
+
+    def __init__(self, object field):
+        cdef dict extra = field.field_info.extra
+        self.annotation = field.outer_type_
+        self.default = PydanticUndefined if field.required else field.default
+        self.default_factory = field.default_factory
+        # v1 fills in the field name as the alias when none was declared
+        self.alias = None if field.alias == field.name else field.alias
+        self.validation_alias = None
+        # Sets and dicts only exclude nested values, which still have to be read
+        self.exclude = field.field_info.exclude is True or field.field_info.exclude is ...
+        self.primary_key = extra.get("primary_key", False)
+        self.is_json = extra.get("is_json", False)
+        self.is_msgpack = extra.get("is_msgpack", False)
+        self.json_path = extra.get("json_path")
+        self.double_decode = extra.get("double_decode", False)
+        self.accept_scalar = extra.get("accept_scalar", False)
+        self.coercions = extra.get("coercions")
+        self.default_json = extra.get("default_json")
+        self.codec = extra.get("codec")
+        self.epoch_unit = extra.get("epoch_unit")
+        self.is_money = extra.get("is_money", False)
+        self.is_bit = extra.get("is_bit", False)
+
+class TypedDictFieldInfo:
+    """
+    A `TypedDict` key exposed through the same `FieldInfo` attributes, so typed dicts can be
+    planned like models. Database options come from a `Field(...)` placed in the key's
+    `Annotated` metadata. A typed dict has no defaults to fall back on, so keys that aren't
+    required (through `NotRequired` or `total=False`) are left out of rows that lack them.
+
+    """
+
+    def __init__(self, object annotation, bint required):
+        cdef object info = None
+        if get_origin(annotation) is Required or get_origin(annotation) is NotRequired:
+            annotation = get_args(annotation)[0]
+        if get_origin(annotation) is Annotated:
+            info = next(
+                (item for item in annotation.__metadata__ if isinstance(item, DBFieldInfo)),
+                None,
+            )
+            annotation = get_args(annotation)[0]
+        self.annotation = annotation
+        self.required = required
+        self.default = PydanticUndefined
+        self.default_factory = None
+        self.alias = None
+        self.validation_alias = None
+        self.exclude = False
+        self.primary_key = getattr(info, "primary_key", False)
+        self.is_json = getattr(info, "is_json", False)
+        self.is_msgpack = getattr(info, "is_msgpack", False)
+        self.json_path = getattr(info, "json_path", None)
+        self.double_decode = getattr(info, "double_decode", False)
+        self.accept_scalar = getattr(info, "accept_scalar", False)
+        self.coercions = getattr(info, "coercions", None)
+        self.default_json = getattr(info, "default_json", None)
+        self.codec = getattr(info, "codec", None)
+        self.epoch_unit = getattr(info, "epoch_unit", None)
+        self.is_money = getattr(info, "is_money", False)
+        self.is_bit = getattr(info, "is_bit", False)
+
+cdef bint is_typed_dict(object model):
+    # Typed dicts have no constructor of their own, so their rows are handed back as dicts
+    return isclass(model) and is_typeddict(model)
+
+cdef bint is_legacy_model(object model):
+    # v2 still answers to `__fields__` with a deprecation warning, so check its own API first
+    return not hasattr(model, "model_fields") and hasattr(model, "__fields__")
+
+cdef dict model_field_infos(object model):
+    if is_typed_dict(model):
+        return {
+            name: TypedDictFieldInfo(annotation, name in model.__required_keys__)
+            for name, annotation in get_type_hints(model, include_extras=True).items()
+        }
+    if is_legacy_model(model):
+        return {name: LegacyFieldInfo(field) for name, field in model.__fields__.items()}
+    return model.get_client_fields()
+
+cdef bint model_allows_extra(object model):
+    if is_typed_dict(model):
+        return False
+    if is_legacy_model(model):
+        return getattr(model.__config__, "extra", None) == "allow"
+    return model.model_config.get("extra") == "allow"
+
+cdef object construct_model(object model, dict fields):
+    # Builds without validation, which v1 names `construct`
+    if is_legacy_model(model):
+        return model.construct(**fields)
+    return model.model_construct(**fields)
+
+cdef object array_leaf_type(object annotation):
+    """
+    Find the element type of a (possibly multi-dimensional) list annotation.
+
+    """
+    cdef tuple args
+    while get_origin(annotation) is list:
+        args = get_args(annotation)
+        annotation = unwrap_optional(args[0]) if args else None
+    return annotation
+
+cdef str model_table_name(object model):
+    # Typed dicts are named the way tables are by default
+    if is_typed_dict(model):
+        return model.__name__.lower()
+    return model.get_table_name()
+
+cdef str table_key_prefix(object table, dict key_prefixes):
+    # Default to the `{table}_{field}` aliases that the query builder emits
+    return key_prefixes.get(table, f"{model_table_name(table)}_")
+
+cdef str instance_key_prefix(object table_alias, dict key_prefixes):
+    # Aliased instances share their model class, so they're only told apart by name
+    return key_prefixes.get(table_alias.name, f"{table_alias.name}_")
+
+cdef str resolve_alias(object info):
+    # AliasPath and AliasChoices describe nested payloads, which don't map onto a flat row key
+    if isinstance(info.validation_alias, str):
+        return info.validation_alias
+    if isinstance(info.alias, str):
+        return info.alias
+    return None
+
+cdef FieldPlan build_model_field_plan(
+    str field,
+    object info,
+    bint is_json,
+    str prefix,
+    bint single_table,
+):
+    """
+    Plan a field that's backed by a model column. Aliased fields are looked up under their
+    alias first, with the field name as a fallback for rows built by our own query builder.
+
+    """
+    cdef str alias = resolve_alias(info)
+    cdef str row_name = field if alias is None else alias
+    cdef list fallbacks = [] if alias is None else [f"{prefix}{field}"]
+
+    # Unprefixed keys are only unambiguous when every selected field comes from the same table
+    if single_table:
+        fallbacks.append(row_name)
+        if alias is not None:
+            fallbacks.append(field)
+
+    cdef FieldPlan plan = FieldPlan(
+        field,
+        f"{prefix}{row_name}",
+        is_json,
+        info.annotation,
+        tuple(fallbacks),
+        row_name,
+        info.default,
+        info.default_factory,
+        info.codec,
+        info.json_path if is_json else None,
+    )
+    plan.is_msgpack = is_json and info.is_msgpack
+    if is_json:
+        plan.default_json = info.default_json
+        plan.double_decode = info.double_decode and not plan.is_msgpack
+        plan.accept_scalar = info.accept_scalar
+    if info.epoch_unit is not None:
+        apply_epoch_unit(plan, info.epoch_unit)
+    if info.is_money:
+        apply_money(plan)
+    if info.is_bit:
+        apply_bit(plan)
+    if is_json:
+        plan.coercions = resolve_coercions(plan, info.coercions)
+    elif info.coercions is not None:
+        raise ValueError(f"coercions only apply to JSON fields, not '{plan.name}'")
+    return plan
+
+cdef object apply_epoch_unit(FieldPlan plan, str epoch_unit):
+    cdef Codec codec
+    if epoch_unit not in EPOCH_CODECS:
+        raise ValueError(
+            f"Unknown epoch_unit '{epoch_unit}' for field '{plan.name}', expected one of "
+            f"{list(EPOCH_CODECS)}"
+        )
+    codec = EPOCH_CODECS[epoch_unit]
+    # Arrays of datetimes read their leaves the same way
+    if plan.codec == CODEC_DATETIME:
+        plan.codec = codec
+    elif plan.codec == CODEC_ARRAY and plan.element_codec == CODEC_DATETIME:
+        plan.element_codec = codec
+    else:
+        raise ValueError(f"epoch_unit only applies to datetime fields, not '{plan.name}'")
+
+cdef tuple resolve_coercions(FieldPlan plan, object coercions):
+    """
+    Order the coercion steps of a JSON field. Without declared steps, the payload is decoded,
+    narrowed to its `json_path`, and then hydrated into its submodel or converted by its
+    codec, whichever of those the field has.
+
+    """
+    cdef list steps = []
+    cdef str name
+
+    if coercions is None:
+        steps.append(COERCE_DECODE)
+        if plan.json_path is not None:
+            steps.append(COERCE_PATH)
+        if plan.submodel is not None:
+            steps.append(COERCE_SUBMODEL)
+        elif plan.codec != CODEC_NONE:
+            steps.append(COERCE_CODEC)
+        return tuple(steps)
+
+    for name in coercions:
+        if name not in COERCIONS:
+            raise ValueError(
+                f"Unknown coercion '{name}' for field '{plan.name}', expected one of "
+                f"{list(COERCIONS)}"
+            )
+        steps.append(COERCIONS[name])
+    if COERCE_PATH in steps and plan.json_path is None:
+        raise ValueError(f"The 'path' coercion of field '{plan.name}' requires a json_path")
+    if COERCE_SUBMODEL in steps and plan.submodel is None:
+        raise ValueError(
+            f"The 'submodel' coercion of field '{plan.name}' requires a pydantic model annotation"
+        )
+    return tuple(steps)
+
+# Currency symbols, then the thousands separator and decimal point, of Postgres' default
+# en_US lc_monetary
+cdef tuple DEFAULT_MONEY_FORMAT = (("$",), ",", ".")
+
+# Thousands separator and decimal point of numeric text, in the same en_US convention
+cdef tuple DEFAULT_NUMERIC_FORMAT = (",", ".")
+
+cdef object apply_money(FieldPlan plan):
+    if plan.codec != CODEC_DECIMAL:
+        raise ValueError(f"is_money only applies to Decimal fields, not '{plan.name}'")
+    plan.codec = CODEC_MONEY
+    # Replaced by the caller's format when it isn't the default, see apply_money_format
+    plan.target_type = DEFAULT_MONEY_FORMAT
+
+# The annotation of an is_bit field picks the representation its bit strings convert to
+cdef dict BIT_CODECS = {
+    str: CODEC_BIT_STRING,
+    int: CODEC_BIT_INT,
+    bytes: CODEC_BIT_BYTES,
+}
+
+cdef object apply_bit(FieldPlan plan):
+    if plan.codec == CODEC_ARRAY or plan.target_type not in BIT_CODECS:
+        raise ValueError(f"is_bit only applies to str, int, or bytes fields, not '{plan.name}'")
+    plan.codec = BIT_CODECS[plan.target_type]
+
+cdef tuple build_field_plans(object select_raw, str prefix, bint single_table):
+    """
+    Plan every field of a table, with column positions counted from the table's first
+    column. Returns the plans along with the number of columns the table takes up.
+
+    """
+    cdef dict infos = model_field_infos(select_raw)
+    cdef list fields = []
+    cdef FieldPlan plan
+    cdef Py_ssize_t position
+
+    # Positions follow every client field, since that's what the query builder selects, even
+    # though excluded fields never make it onto the model
+    for position, (field, info) in enumerate(infos.items()):
+        if info.exclude:
+            continue
+        # msgpack blobs decode into the same structures as JSON text, so they share its path
+        plan = build_model_field_plan(
+            field, info, info.is_json or info.is_msgpack, prefix, single_table
+        )
+        plan.column_index = position
+        if type(info) is TypedDictFieldInfo:
+            plan.optional_key = not info.required
+        fields.append(plan)
+    return fields, len(infos)
+
+# Table field plans only depend on the model and how its keys are prefixed, so they're shared
+# across calls. Entries hold a reference to their model class, which keeps the key from being
+# reused by another class, and the least recently used entry is evicted past the size limit.
+# Each entry holds one plan per field, so memory is bounded by the plans of 256 tables no
+# matter how many rows or calls go through them.
+cdef object PLAN_CACHE = OrderedDict()
+cdef Py_ssize_t PLAN_CACHE_SIZE = 256
+cdef Py_ssize_t plan_cache_hits = 0
+cdef Py_ssize_t plan_cache_misses = 0
+
+cdef tuple cached_field_plans(object model, str prefix, bint single_table):
+    """
+    The table's field plans and column count, as returned by `build_field_plans`. The plans
+    are shared with later calls, so they have to be copied before anything is set on them.
+
+    """
+    global plan_cache_hits, plan_cache_misses
+    cdef tuple key = (model, prefix, single_table)
+    cdef tuple entry = PLAN_CACHE.get(key)
+
+    if entry is None:
+        plan_cache_misses += 1
+        entry = build_field_plans(model, prefix, single_table)
+        PLAN_CACHE[key] = entry
+        if len(PLAN_CACHE) > PLAN_CACHE_SIZE:
+            PLAN_CACHE.popitem(last=False)
+    else:
+        plan_cache_hits += 1
+        PLAN_CACHE.move_to_end(key)
+    return entry
+
+cdef list place_field_plans(list cached_fields, Py_ssize_t offset):
+    cdef FieldPlan field
+    return [field.copy(offset) for field in cached_fields]
+
+def plan_cache_info() -> Dict[str, int]:
+    """
+    Hit and miss counts of the table plan cache since the last `clear_plan_cache`, along with
+    its current and maximum number of entries.
+
+    """
+    return {
+        "hits": plan_cache_hits,
+        "misses": plan_cache_misses,
+        "size": len(PLAN_CACHE),
+        "max_size": PLAN_CACHE_SIZE,
+    }
+
+def clear_plan_cache() -> None:
+    """
+    Drop every cached table plan and reset the counters, like after redefining a model's
+    fields at runtime.
+
+    """
+    global plan_cache_hits, plan_cache_misses
+    PLAN_CACHE.clear()
+    plan_cache_hits = 0
+    plan_cache_misses = 0
+
+cdef object check_table_select(object select_raw, Py_ssize_t select_index):
+    # Anything else would only fail deep inside planning, with an error about a missing
+    # attribute. Plain pydantic models included, since they have no table name or client fields.
+    if isclass(select_raw) and (
+        issubclass(select_raw, TableBase)
+        or is_legacy_model(select_raw)
+        or is_typed_dict(select_raw)
+    ):
+        return None
+    cdef str received = (
+        f"class {select_raw.__name__}" if isclass(select_raw) else type(select_raw).__name__
+    )
+    raise TypeError(
+        f"Select index {select_index} is flagged as a table, but got {received} instead of a "
+        "model class"
+    )
+
+cdef list build_select_plans(list select_raws, list select_types, dict key_prefixes):
+    cdef list plans = []
+    cdef set tables = set()
     cdef object select_raw
+    cdef SelectPlan plan
+    cdef Py_ssize_t select_index
+    cdef FieldPlan column
     cdef bint raw_is_table, raw_is_column, raw_is_function_metadata
+    cdef bint single_table
+    cdef Py_ssize_t offset = 0
+    cdef str prefix
+    cdef tuple cached
 
-    if not fields:
-        raise MemoryError("Failed to allocate memory for fields.")
+    # zip would otherwise drop the unpaired selects and hand back short rows
+    if len(select_raws) != len(select_types):
+        raise ValueError(
+            f"Got {len(select_raws)} select_raws but {len(select_types)} select_types; "
+            "every select needs exactly one type entry"
+        )
 
-    for j in range(num_selects):
-        select_raw = select_raws[j]
-        raw_is_table, raw_is_column, raw_is_function_metadata = select_types[j]
+    for select_index in range(len(select_raws)):
+        select_raw = select_raws[select_index]
+        raw_is_table, raw_is_column, raw_is_function_metadata = select_types[select_index]
+        if raw_is_table:
+            check_table_select(select_raw, select_index)
+            tables.add(select_raw)
+        elif raw_is_column:
+            tables.add(select_raw.root_model)
+        elif isinstance(select_raw, TableAlias):
+            # Each alias is its own instance, so a self-join never counts as a single table
+            tables.add(select_raw)
 
+    single_table = len(tables) == 1
+
+    for select_raw, (raw_is_table, raw_is_column, raw_is_function_metadata) in zip(select_raws, select_types):
         if raw_is_table:
-            field_dict = {field: info.is_json for field, info in select_raw.get_client_fields().items() if not info.exclude}
-            num_fields = len(field_dict)
-            num_fields_array[j] = num_fields
-            fields[j] = <FieldInfo*>malloc(num_fields * sizeof(FieldInfo))
-            if not fields[j]:
-                raise MemoryError("Failed to allocate memory for FieldInfo.")
+            cached = cached_field_plans(
+                select_raw, table_key_prefix(select_raw, key_prefixes), single_table
+            )
+            plans.append(
+                SelectPlan(
+                    select_raw,
+                    SELECT_TABLE,
+                    model_table_name(select_raw),
+                    None,
+                    place_field_plans(cached[0], offset),
+                )
+            )
+            offset += cached[1]
+        elif isinstance(select_raw, TableAlias):
+            # Planned like the table itself, with the alias standing in for the table name
+            prefix = instance_key_prefix(select_raw, key_prefixes)
+            cached = cached_field_plans(select_raw.table, prefix, single_table)
+            plan = SelectPlan(
+                select_raw.table,
+                SELECT_TABLE,
+                select_raw.name,
+                None,
+                place_field_plans(cached[0], offset),
+            )
+            plan.key_prefix = prefix
+            plans.append(plan)
+            offset += cached[1]
+        elif raw_is_column:
+            # Use the table-qualified column name
+            column = build_model_field_plan(
+                select_raw.key,
+                select_raw.field_definition,
+                False,
+                table_key_prefix(select_raw.root_model, key_prefixes),
+                single_table,
+            )
+            column.column_index = offset
+            offset += 1
+            plans.append(SelectPlan(select_raw, SELECT_COLUMN, column.name, column, None))
+        elif raw_is_function_metadata:
+            if select_raw.output_alias is not None:
+                column = FieldPlan(
+                    select_raw.local_name,
+                    select_raw.output_alias,
+                    False,
+                    None,
+                    (select_raw.local_name,),
+                )
+            else:
+                column = FieldPlan(select_raw.local_name, select_raw.local_name, False, None)
+            column.column_index = offset
+            offset += 1
+            plans.append(SelectPlan(select_raw, SELECT_FUNCTION, column.name, column, None))
+        elif isinstance(select_raw, Alias):
+            column = FieldPlan(select_raw.name, select_raw.name, False, select_raw.type)
+            column.column_index = offset
+            offset += 1
+            plans.append(SelectPlan(select_raw, SELECT_ALIAS, column.name, column, None))
+        else:
+            plans.append(SelectPlan(select_raw, SELECT_UNKNOWN, None, None, None))
+
+    for select_index, plan in enumerate(plans):
+        if plan.kind == SELECT_TABLE:
+            for column in plan.fields:
+                column.select_index = select_index
+        elif plan.kind != SELECT_UNKNOWN:
+            plan.column.select_index = select_index
+
+    plan_extra_columns(plans, key_prefixes, single_table)
+    return plans
+
+cdef object plan_extra_columns(list plans, dict key_prefixes, bint single_table):
+    """
+    Let table selects whose model is configured with `extra="allow"` pick up the row keys
+    that no select maps onto, so computed columns land on the model as extra attributes.
+
+    """
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef set mapped_keys = set()
+    cdef list extra_plans = []
+    cdef frozenset frozen_keys
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            if model_allows_extra(plan.select_raw):
+                extra_plans.append(plan)
+            for field in plan.fields:
+                mapped_keys.add(field.select_attribute)
+                mapped_keys.update(field.fallback_attributes)
+        elif plan.kind != SELECT_UNKNOWN:
+            mapped_keys.add(plan.column.select_attribute)
+            mapped_keys.update(plan.column.fallback_attributes)
+
+    if not extra_plans:
+        return None
+
+    frozen_keys = frozenset(mapped_keys)
+    for plan in extra_plans:
+        if plan.key_prefix is None:
+            plan.key_prefix = table_key_prefix(plan.select_raw, key_prefixes)
+        # With other tables in the select, only keys under this table's prefix are ours to claim
+        plan.extra_prefix = "" if single_table else plan.key_prefix
+        plan.mapped_keys = frozen_keys
+    return None
+
+cdef object collect_extra_columns(object value, SelectPlan plan, dict obj_dict):
+    cdef object key
+    for key in value.keys():
+        if key in plan.mapped_keys or not key.startswith(plan.extra_prefix):
+            continue
+        # Keys under the table's own prefix are stored without it, like declared fields
+        obj_dict[key.removeprefix(plan.key_prefix)] = value[key]
+    return None
+
+# Everything pydantic's own bool validation accepts, so casting never rejects a value that
+# pydantic would have taken
+cdef frozenset BOOL_TRUE_STRINGS = frozenset(("1", "on", "t", "true", "y", "yes"))
+cdef frozenset BOOL_FALSE_STRINGS = frozenset(("0", "off", "f", "false", "n", "no"))
+
+cdef object epoch_to_datetime(object seconds):
+    try:
+        return datetime.fromtimestamp(seconds, timezone.utc)
+    except (OverflowError, OSError) as e:
+        # Surfaced as a ValueError so the error names the field and row like other bad values
+        raise ValueError(f"Epoch timestamp {seconds!r} is out of range") from e
+
+cdef object cast_scalar(Codec codec, object target_type, object value):
+    """
+    Convert one value to the type requested by `codec`. Values that already have the right
+    type pass through untouched. NULLs are handled here once for every codec, so none of the
+    branches below ever see None.
+
+    """
+    if value is None:
+        return None
+    if codec == CODEC_DATETIME:
+        if isinstance(value, str):
+            return datetime.fromisoformat(value)
+    elif codec == CODEC_EPOCH_SECONDS or codec == CODEC_EPOCH_MILLIS:
+        if isinstance(value, str):
+            return datetime.fromisoformat(value)
+        # bool is an int subclass, but it's never a timestamp
+        if isinstance(value, (int, float)) and not isinstance(value, bool):
+            return epoch_to_datetime(value / 1000 if codec == CODEC_EPOCH_MILLIS else value)
+    elif codec == CODEC_DATE:
+        if isinstance(value, str):
+            return date.fromisoformat(value)
+    elif codec == CODEC_TIME:
+        if isinstance(value, str):
+            return time.fromisoformat(value)
+    elif codec == CODEC_INTERVAL:
+        if isinstance(value, str):
+            return parse_interval(value)
+    elif codec == CODEC_UUID:
+        if isinstance(value, str):
+            return UUID(value)
+    elif codec == CODEC_DECIMAL:
+        if isinstance(value, float):
+            # Go through the shortest repr so we don't inherit binary float artifacts
+            return Decimal(str(value))
+        if isinstance(value, str):
+            return Decimal(normalize_numeric(value, target_type))
+        if isinstance(value, int):
+            return Decimal(value)
+    elif codec == CODEC_MONEY:
+        if isinstance(value, str):
+            return parse_money(value, target_type)
+        if isinstance(value, float):
+            return Decimal(str(value))
+        if isinstance(value, int):
+            return Decimal(value)
+    elif codec == CODEC_BIT_STRING or codec == CODEC_BIT_INT or codec == CODEC_BIT_BYTES:
+        return parse_bits(codec, value)
+    elif codec == CODEC_INT:
+        if isinstance(value, str):
+            return int(value)
+    elif codec == CODEC_FLOAT:
+        # float() also reads the "NaN", "Infinity", and "-Infinity" that Postgres emits as text
+        if isinstance(value, str):
+            return float(normalize_numeric(value, target_type))
+    elif codec == CODEC_BOOL:
+        if value is True or value is False:
+            return value
+        # Drivers without a native boolean surface them as 0/1 or Postgres' own 't'/'f'
+        if isinstance(value, (int, float)):
+            if value == 1:
+                return True
+            if value == 0:
+                return False
+        elif isinstance(value, str):
+            if value.lower() in BOOL_TRUE_STRINGS:
+                return True
+            if value.lower() in BOOL_FALSE_STRINGS:
+                return False
+        raise ValueError(f"Unrecognized boolean {value!r}")
+    elif codec == CODEC_ENUM:
+        if not isinstance(value, target_type):
+            return target_type(value)
+    elif codec == CODEC_BYTES:
+        if isinstance(value, (memoryview, bytearray)):
+            return bytes(value)
+    elif codec == CODEC_MEMORYVIEW:
+        # Exposes the driver's buffer directly, without copying it
+        if not isinstance(value, memoryview):
+            return memoryview(value)
+    elif codec == CODEC_PATH:
+        # Paths are stored as text, so anything else means the column isn't really a path
+        if not isinstance(value, (str, PurePath)):
+            raise ValueError(f"Expected a path string, got {type(value).__name__}")
+        if not isinstance(value, target_type):
+            return target_type(value)
+    elif codec == CODEC_CUSTOM:
+        # Registered decoders always run, since we can't tell whether the value is decoded yet
+        return target_type(value)
+    return value
+
+cdef object parse_bits(Codec codec, object value):
+    """
+    Convert a `bit` or `varbit` value, like "1010", into the representation requested by
+    `codec`. Ints and bytes that are already converted pass through.
+
+    """
+    cdef str bits
+    cdef Py_ssize_t padding
+
+    if codec == CODEC_BIT_INT and isinstance(value, int):
+        return value
+    if codec == CODEC_BIT_BYTES and isinstance(value, bytes):
+        return value
+    if isinstance(value, str):
+        bits = value
+    elif hasattr(value, "as_string"):
+        # asyncpg's BitString, which groups its digits with spaces
+        bits = value.as_string().replace(" ", "")
+    else:
+        raise ValueError(f"Expected a bit string, got {type(value).__name__}")
+    if bits.strip("01"):
+        raise ValueError(f"Invalid bit string {bits!r}")
+
+    if codec == CODEC_BIT_STRING:
+        return bits
+    if not bits:
+        return 0 if codec == CODEC_BIT_INT else b""
+    if codec == CODEC_BIT_INT:
+        return int(bits, 2)
+    # Left-aligned like Postgres stores them, so the last byte is padded with zero bits
+    padding = -len(bits) % 8
+    return (int(bits, 2) << padding).to_bytes((len(bits) + padding) // 8, "big")
+
+cdef str normalize_numeric(str text, object numeric_format):
+    """
+    Rewrite numeric text, like "1,234.56", into the plain form that Decimal and float read.
+    `numeric_format` holds the thousands separator and decimal point, and anything other than
+    a tuple (like the annotation of a field that never had a format applied) means the default.
+
+    """
+    cdef tuple separators = (
+        numeric_format if type(numeric_format) is tuple else DEFAULT_NUMERIC_FORMAT
+    )
+    cdef str thousands = separators[0]
+    cdef str point = separators[1]
+    cdef str integer, found, fraction
+    cdef list groups
+
+    integer, found, fraction = text.partition(point)
+    # Text in the other format, like "1.234,56" read with the default, has separators after
+    # its first decimal point, and would otherwise silently read as a different number
+    if point in fraction or thousands in fraction:
+        raise ValueError(f"Misplaced separators in {text!r}")
+    if thousands in integer:
+        # Only strip separators that group thousands, so "0,5" in the wrong format fails
+        # instead of reading as 5
+        groups = integer.strip().lstrip("+-").split(thousands)
+        if not 1 <= len(groups[0]) <= 3 or any(len(group) != 3 for group in groups[1:]):
+            raise ValueError(f"Misplaced separators in {text!r}")
+        integer = integer.replace(thousands, "")
+    return f"{integer}.{fraction}" if found else integer
+
+cdef object parse_money(str text, tuple money_format):
+    """
+    Read a formatted `money` value, like "$1,234.56" or "($5.00)", into a Decimal.
+    `money_format` holds the currency symbols, then the thousands separator and decimal point.
+
+    """
+    cdef str symbol
+    cdef bint negative = False
+
+    text = text.strip()
+    # Accounting style negatives, next to the usual leading minus
+    if text.startswith("(") and text.endswith(")"):
+        negative = True
+        text = text[1:-1]
+    for symbol in money_format[0]:
+        text = text.replace(symbol, "")
+    # Checked like other numeric text, so "$1.234,56" in the wrong format fails instead of
+    # reading as 1.23456
+    text = normalize_numeric(text.strip(), money_format[1:])
+    return Decimal(f"-{text}" if negative else text)
 
-            for k, (field, is_json) in enumerate(field_dict.items()):
-                select_bytes = f"{select_raw.get_table_name()}_{field}".encode('utf-8')
-                c_select = allocate_cstring(select_bytes)
+# timedelta has no notion of calendar months, so follow Postgres' own justify_days and treat
+# a month as 30 days. Postgres stores a year as exactly 12 months, so it's 360 days rather
+# than 365, which keeps "1 year" and "12 mons" equal.
+cdef dict INTERVAL_UNIT_DAYS = {
+    "year": 360,
+    "years": 360,
+    "mon": 30,
+    "mons": 30,
+    "day": 1,
+    "days": 1,
+}
 
-                field_bytes = field.encode('utf-8')
-                c_field = allocate_cstring(field_bytes)
+cdef object parse_interval_clock(str token):
+    cdef int sign = -1 if token.startswith("-") else 1
+    cdef list parts = token.lstrip("+-").split(":")
+    cdef str seconds
+    cdef str fraction
 
-                fields[j][k].select_attribute = c_select
-                fields[j][k].name = c_field
-                fields[j][k].is_json = is_json
+    if len(parts) != 3:
+        raise ValueError(f"Malformed interval time {token!r}")
+
+    seconds, _, fraction = parts[2].partition(".")
+    return sign * timedelta(
+        hours=int(parts[0]),
+        minutes=int(parts[1]),
+        seconds=int(seconds),
+        microseconds=int(fraction.ljust(6, "0")[:6]) if fraction else 0,
+    )
+
+cdef object parse_interval(str text):
+    """
+    Parse an interval in Postgres' default output style, like `1 day 02:03:04`, `-3 days`, or
+    `1 year 2 mons 00:00:05.5`, into a timedelta.
+
+    """
+    cdef list tokens = text.split()
+    cdef object total = timedelta()
+    cdef Py_ssize_t i = 0
+
+    if not tokens:
+        raise ValueError("Empty interval")
+
+    while i < len(tokens):
+        if ":" in tokens[i]:
+            total += parse_interval_clock(tokens[i])
+            i += 1
+        elif i + 1 < len(tokens) and tokens[i + 1] in INTERVAL_UNIT_DAYS:
+            total += timedelta(days=int(tokens[i]) * INTERVAL_UNIT_DAYS[tokens[i + 1]])
+            i += 2
         else:
-            num_fields_array[j] = 0
-            fields[j] = NULL
+            raise ValueError(f"Unexpected interval component {tokens[i]!r}")
 
-    return fields
+    return total
 
-cdef list process_values(
-    list values,
-    FieldInfo** fields,
-    Py_ssize_t* num_fields_array,
-    list select_raws,
-    list select_types,
-    Py_ssize_t num_selects
+cdef Py_ssize_t skip_whitespace(str text, Py_ssize_t position):
+    while position < len(text) and text[position].isspace():
+        position += 1
+    return position
+
+cdef tuple parse_array_level(str text, Py_ssize_t position, FieldPlan field):
+    """
+    Parse one brace-delimited level of a Postgres array literal starting at `position`.
+    Returns the parsed list and the position just past its closing brace.
+
+    """
+    cdef Py_ssize_t length = len(text)
+    cdef Py_ssize_t start
+    cdef list items = []
+    cdef list characters
+    cdef str token
+    cdef object item
+
+    if position >= length or text[position] != "{":
+        raise ValueError("Array literal must start with '{'")
+
+    position = skip_whitespace(text, position + 1)
+    if position < length and text[position] == "}":
+        return items, position + 1
+
+    while True:
+        position = skip_whitespace(text, position)
+        if position >= length:
+            raise ValueError("Unterminated array literal")
+
+        if text[position] == "{":
+            item, position = parse_array_level(text, position, field)
+            items.append(item)
+        elif text[position] == '"':
+            # Quoted elements can hold delimiters, and backslashes escape the next character
+            characters = []
+            position += 1
+            while True:
+                if position >= length:
+                    raise ValueError("Unterminated quoted array element")
+                if text[position] == "\\":
+                    position += 1
+                    if position >= length:
+                        raise ValueError("Unterminated quoted array element")
+                elif text[position] == '"':
+                    position += 1
+                    break
+                characters.append(text[position])
+                position += 1
+            items.append(cast_scalar(field.element_codec, field.target_type, "".join(characters)))
+        else:
+            start = position
+            while position < length and text[position] not in ",}":
+                position += 1
+            token = text[start:position].strip()
+            # Only the unquoted keyword is a NULL, "NULL" in quotes is a literal string
+            if token.upper() == "NULL":
+                items.append(None)
+            else:
+                items.append(cast_scalar(field.element_codec, field.target_type, token))
+
+        position = skip_whitespace(text, position)
+        if position >= length:
+            raise ValueError("Unterminated array literal")
+        if text[position] == ",":
+            position += 1
+        elif text[position] == "}":
+            return items, position + 1
+        else:
+            raise ValueError(f"Unexpected character {text[position]!r} in array literal")
+
+cdef list parse_array_literal(str text, FieldPlan field):
+    cdef Py_ssize_t position
+    cdef list items
+
+    # Arrays with non-default bounds are prefixed by their dimensions, like `[0:2]={1,2,3}`
+    if text.startswith("["):
+        position = text.find("=")
+        if position == -1:
+            raise ValueError("Malformed array dimensions")
+        text = text[position + 1:]
+
+    text = text.strip()
+    items, position = parse_array_level(text, 0, field)
+    if position != len(text):
+        raise ValueError("Unexpected trailing characters after array literal")
+    return items
+
+cdef list convert_array_leaves(FieldPlan field, list items):
+    cdef list converted = []
+    cdef object item
+    for item in items:
+        if isinstance(item, list):
+            converted.append(convert_array_leaves(field, item))
+        else:
+            converted.append(cast_scalar(field.element_codec, field.target_type, item))
+    return converted
+
+cdef object coerce_value(FieldPlan field, object field_value, Py_ssize_t row_index):
+    """
+    Convert a value into the type declared by the field. Every codec is dispatched from here,
+    or from `cast_scalar` for the leaves of arrays, and NULLs bypass both, so callers don't
+    need their own checks.
+
+    """
+    if field_value is None:
+        return None
+    try:
+        if field.codec == CODEC_ARRAY:
+            if field.is_json:
+                # A list annotation promises an array, so a bare scalar is a mismatch unless
+                # the field opted into single values
+                if not isinstance(field_value, list):
+                    if not field.accept_scalar:
+                        raise ValueError("Expected a JSON array")
+                    return cast_scalar(field.element_codec, field.target_type, field_value)
+                return convert_array_leaves(field, field_value)
+            # Drivers normally decode arrays already, but they can also surface as text literals
+            if isinstance(field_value, str):
+                return parse_array_literal(field_value, field)
+            # Epoch numbers come back from the driver as a plain numeric array
+            if isinstance(field_value, list) and (
+                field.element_codec == CODEC_EPOCH_SECONDS
+                or field.element_codec == CODEC_EPOCH_MILLIS
+            ):
+                return convert_array_leaves(field, field_value)
+            return field_value
+        return cast_scalar(field.codec, field.target_type, field_value)
+    except (ValueError, InvalidOperation) as e:
+        raise ValueError(
+            f"Invalid value for '{field.name}' in row {row_index} "
+            f"(select index {field.select_index}): {field_value!r}"
+        ) from e
+
+cdef enum OutputMode:
+    OUTPUT_MODEL
+    OUTPUT_DICT
+    OUTPUT_NAMEDTUPLE
+
+cdef dict OUTPUT_MODES = {
+    "model": OUTPUT_MODEL,
+    "dict": OUTPUT_DICT,
+    "merged_dict": OUTPUT_DICT,  # Flattens the tables as well, see CastingOptions
+    "namedtuple": OUTPUT_NAMEDTUPLE,
+}
+
+cdef enum RowFormat:
+    ROW_DICT
+    ROW_TUPLE
+    ROW_ATTR
+
+cdef dict ROW_FORMATS = {
+    "dict": ROW_DICT,
+    "tuple": ROW_TUPLE,
+    "attr": ROW_ATTR,
+}
+
+cdef enum DuplicatePolicy:
+    DUPLICATE_ERROR
+    DUPLICATE_LAST
+    DUPLICATE_SUFFIX
+
+cdef dict DUPLICATE_POLICIES = {
+    "error": DUPLICATE_ERROR,
+    "last": DUPLICATE_LAST,
+    "suffix": DUPLICATE_SUFFIX,
+}
+
+cdef enum ValuePolicy:
+    POLICY_RAISE
+    POLICY_NONE
+    POLICY_DEFAULT
+    POLICY_SKIP
+
+# Shared by missing_policy and null_policy
+cdef dict VALUE_POLICIES = {
+    "raise": POLICY_RAISE,
+    "none": POLICY_NONE,
+    "default": POLICY_DEFAULT,
+    "skip": POLICY_SKIP,
+}
+
+cdef class CastingOptions:
+    """
+    Per-call settings for the casting pipeline. The public entrypoints accept these as keyword
+    arguments and build the options once, so the row loop only carries a single reference.
+
+    :param strict: If True, raise a KeyError when a selected key is missing from a row. If False,
+        missing model fields fall back to their declared `default` or `default_factory`, and
+        missing columns, functions, and aliases resolve to None. Shorthand for `missing_policy`
+        "raise" and "default", and ignored when `missing_policy` is set.
+    :param missing_policy: What happens to a selected key that's absent from a row. "raise"
+        raises a KeyError, "default" fills model fields from their declared `default` or
+        `default_factory` and leaves out fields without one, "none" sets every missing field
+        to None regardless of its default, and "skip" leaves every missing field out, so the
+        model's own constructor decides, like pydantic filling defaults or rejecting required
+        fields. Columns, functions, and aliases have no key to leave out or default to fall
+        back on, so every policy but "raise" resolves them to None. Defaults to "raise", or
+        "default" when `strict` is False. `on_missing` and `warn_on_missing` run first, for
+        every policy but "raise".
+    :param null_policy: What happens to a selected key whose value is a SQL NULL. "none" (the
+        default) keeps the None, "raise" raises a ValueError like listing every select in
+        `non_null_fields`, "default" swaps in the field's declared default (None without
+        one), and "skip" leaves the field out, like `missing_policy` "skip". Columns,
+        functions, and aliases stay None for "default" and "skip". The two policies are
+        independent, since a missing key is never a NULL, and a table whose fields are all
+        missing or NULL still resolves to None under every combination that doesn't raise.
+    :param on_missing: Callback for computing the replacement of a value that's missing from
+        a row when `strict` is False. Called with the field name (or the column key, function
+        local name, or alias name) and the row index, and its return value is used in place
+        of the missing value. Returning `SKIP_MISSING` falls back to the usual defaults and
+        None. Replacements don't count as values, so a table whose fields are all missing or
+        NULL still resolves to None.
+    :param warn_on_missing: If True, emit a `MissingColumnWarning` when `strict` is False and a
+        selected key is missing. Each key warns once per call, on the first row that lacks it,
+        so a column dropped from every row doesn't flood the warning filters.
+    :param json_loads: Deserializer for `is_json` fields, like `orjson.loads`. Called with the
+        column value, with bytes-like payloads decoded from UTF-8 first. Values that the driver
+        already decoded, like dicts and lists, skip the loader, and values of any other type
+        raise a ValueError naming the field and row. Defaults to the stdlib
+        `json.loads`. When the field is annotated with a pydantic model (or a list of them), the
+        parsed objects are hydrated into that model. Fields annotated with a type that JSON
+        can't represent, like UUID or Decimal, have their parsed value converted to it. For a
+        list of such a type, each element is converted, and a parsed value that isn't an array
+        is rejected, unless the field sets `accept_scalar` to have it converted on its own.
+    :param unpackb: Deserializer for `is_msgpack` fields, like `msgpack.unpackb`. Called with
+        the column's bytes or memoryview, and its result goes through the same submodel
+        hydration and conversions as a parsed JSON value. Values that aren't bytes-like are
+        assumed to be decoded already. Required when an `is_msgpack` field is selected.
+    :param json_strict: If True, `NaN`, `Infinity`, and `-Infinity` in JSON payloads raise a
+        `NonFiniteJSONError` instead of parsing into floats, as strict JSON requires. Only
+        applies to the default stdlib loader, so it can't be combined with `json_loads`.
+    :param batch_json: If True, parse each JSON field's payloads across the whole result with
+        a single `json_loads` call, by joining them into one JSON array, instead of calling it
+        once per row. This cuts the per-call overhead on results with many small payloads.
+        Applies when `values` is a list and the column holds only JSON text, and falls back
+        to per-row parsing otherwise, or when the joined array doesn't parse. Payloads are
+        joined as-is, so it's only safe for `json` and `jsonb` columns, which Postgres
+        guarantees hold one valid JSON value each.
+    :param validate: If False, build table objects and JSON submodels with `model_construct`
+        instead of `__init__`. This skips pydantic validation and coercion, so it's only safe
+        when the database types already match the model. Pydantic v1 models, which are told
+        apart by their `__fields__`, are built with `construct` instead.
+    :param key_prefixes: Row key prefix per table class, like `{UserDemo: "users_"}`, for rows
+        whose columns were aliased by hand. Tables that aren't listed use the `{table_name}_`
+        prefix generated by the query builder. When every selected field comes from a single
+        table, unprefixed keys are accepted as a fallback. Fields that declare a pydantic `alias`
+        or `validation_alias` are read from `prefix + alias`, then from `prefix + field`.
+        Instances selected with `alias_table` are listed by their alias name instead, like
+        `{"manager": "m_"}`, and default to the `{alias}_` prefix.
+    :param output_mode: "model" to build table objects, "dict" to return each row as a plain
+        dict, or "namedtuple" to return each row as a namedtuple. In dict and namedtuple mode,
+        column, function, and alias selects are keyed by their key, local name, or alias name.
+        Table selects contribute a dict or namedtuple of their fields, nested under the table
+        name unless the table is the only item selected. The namedtuple types are created once
+        per call and shared by every row. "merged_dict" returns each row as a single flat dict
+        instead, with the fields of every table select merged in next to the other selects. An
+        entirely NULL table contributes None for each of its fields.
+    :param row_factory: Callable that builds each row's result from the dict that "dict" output
+        would return, for output objects like dataclasses or msgspec structs. Setting it
+        switches the output mode to "dict", so it can't be combined with "namedtuple". Rows
+        whose only select is a table that's entirely NULL stay None.
+    :param transforms: Callables to run on individual values once they're fetched and
+        converted, keyed by the default row key: `{table_name}_{field}` for model fields and
+        columns, the local name for functions, or the alias name. Transforms also receive
+        None for SQL NULLs, and their return value is used as-is.
+    :param non_null_fields: Fields and columns that must never be NULL, as a data-quality check
+        during casting. Keyed like `transforms`. A SQL NULL in any of them raises a ValueError
+        naming the field and row, but a table whose fields are all NULL, like the missing side
+        of a LEFT JOIN, still resolves to None. Names that don't match any select raise a
+        ValueError up front, so a typo can't silently disable the check.
+    :param collect_timings: If True, `optimize_exec_casting`, `optimize_exec_auto`, and
+        `PreparedSelect.materialize` return a `(results, timings)` tuple, where `timings` maps
+        each phase ("plan_build", "json_parse", "construct", "column_extract") to the
+        nanoseconds spent on it. JSON parsing includes submodel hydration, and isn't counted
+        again under column extraction. The clock is never read when this is off. The other
+        entrypoints return their results alone, so they raise a ValueError when it's set.
+    :param track_provenance: If True, `optimize_exec_casting`, `optimize_exec_auto`, and
+        `PreparedSelect.materialize` also return where every value came from, as a list with one
+        tuple per row and one entry per select. Table selects map each field name to its state,
+        and other selects hold the state directly. States are "present" for values read from the
+        row, "null" for SQL NULLs, "default" for missing fields filled from their declared
+        default, and "missing" for missing keys without one. The list is appended after the
+        results (and timings, if collected). Rows from iterables other than lists are buffered
+        into a list first, since they're inspected twice. The other entrypoints raise a
+        ValueError when it's set.
+    :param case_insensitive: If True, keys that don't match exactly are looked up again while
+        ignoring case, for databases that uppercase unquoted identifiers. Exact matches
+        still take precedence. Each row's keys are only folded once, on its first miss.
+    :param row_format: "dict" for rows keyed by column name, or "tuple" for positional rows
+        like psycopg's default row factory returns. Keyed rows can be any mapping, like
+        asyncpg's `Record` or SQLAlchemy's `RowMapping`, since they're only read with
+        subscripts and, for `case_insensitive` and extra columns, `keys()`. Missing keys have
+        to raise KeyError, as the mapping protocol expects. Positional rows follow the select order:
+        each table takes one slot per client field, and each column, function, and alias
+        takes one slot. Key prefixes and aliases don't apply to positional rows. "attr" is
+        for row objects that expose their columns as attributes, like SQLAlchemy's `Row`,
+        which are read with `getattr` under the same names as dict keys. Missing attributes
+        are handled like missing keys, but `case_insensitive` doesn't apply to them. Rows
+        that can't be read in the format, like a None that ended up in `values`, raise a
+        TypeError naming the row, which `optimize_exec_lenient` reports like other errors.
+    :param codecs: Decoders keyed by the tag that fields declare with `Field(codec=...)`, like
+        `{"inet": ip_address}`. A registered decoder replaces the built-in conversion for that
+        field's non-null values, including values of column selects. Tags without a decoder
+        leave the field's values to the built-in conversions.
+    :param money_symbols: Currency symbols to strip from fields declared with
+        `Field(is_money=True)`, like `("€",)`. Defaults to `("$",)`, to match Postgres'
+        default lc_monetary.
+    :param money_separators: Thousands separator and decimal point of `is_money` fields, as
+        a tuple like `(".", ",")` for "1.234,56". Defaults to `numeric_separators`, or
+        `(",", ".")` when neither is given.
+    :param numeric_separators: Thousands separator and decimal point to read text values of
+        Decimal and float fields with, as a tuple like `(".", ",")` for "1.234,56". Defaults
+        to `(",", ".")`, so "1,234.56" reads as 1234.56. Array elements are always read in
+        the default format, since that's how Postgres writes array literals.
+    :param rename: Output keys to use in place of the default ones in dict output (including
+        `optimize_exec_columnar` and `optimize_exec_json`), like `{"created_at": "createdAt"}`.
+        Matched against table names, model field names, column keys, function local names,
+        and alias names. Names that aren't listed keep their key, and the other output modes
+        ignore the mapping.
+    :param on_duplicate: What to do when two selects produce the same key in dict or columnar
+        output, like the same column selected twice. "last" lets the later select overwrite
+        the earlier one, "error" raises a ValueError, and "suffix" keeps the first key and
+        numbers the rest, like `name_1`. Defaults to "error" for "merged_dict" output and to
+        "last" otherwise. Columnar and "merged_dict" output check the keys of table fields as
+        well, since they flatten them. Keys are checked after `rename` and
+        `prefix_table_keys`.
+    :param prefix_table_keys: If True, the fields of tables flattened by "merged_dict" or
+        columnar output are keyed by `{table_name}_{field}`, like `userdemo_name`, so the
+        same field name on two tables doesn't collide.
+    :param binary_as_memoryview: How to return the values of `bytes` fields, which some drivers
+        hand back as memoryviews. None (the default) keeps whatever the driver returned, True
+        wraps every value in a memoryview over the original buffer without copying it, and
+        False copies every value into `bytes`. Pydantic validation only accepts `bytes`, so
+        True is meant for the dict output mode or `validate=False`.
+    :param construct_style: How table objects are passed their field values. "keyword" (the
+        default) calls the model with keyword arguments only, and "positional" passes them
+        as positional arguments in the order the model declares its fields, for models with
+        a custom `__init__` that takes them that way. Excluded fields don't take a slot, and
+        fields missing from a lenient row are passed their declared default, or None, so
+        the later arguments don't shift. Unmapped columns of `extra="allow"` models are
+        still passed by keyword. Only applies to the validating "model" output mode.
+    :param dedup_by_pk: If True, table selects return the object they already built for an
+        earlier row with the same primary key, instead of building a new one. This keeps
+        repeated parent rows of a join identical by identity. Rows with a NULL primary key and
+        tables without a primary key are always built fresh.
+    :param lazy: If True, table selects return a `LazyModel` proxy that holds the cast field
+        values and only builds the model on its first attribute read. This saves building
+        wide models whose fields are mostly left untouched, but moves validation errors from
+        casting to that first read. Only applies to the "model" output mode.
+    :param freeze: If True, table objects reject field assignments with a `FrozenModelError`,
+        to guard results that are shared downstream against accidental mutation. Models
+        configured with `frozen=True` already do, and are returned as is. Only applies to the
+        "model" output mode.
+    :param on_unfreezable: What `freeze` does with table selects whose model can't be frozen,
+        because it's neither a `TableBase` nor configured as frozen. "ignore" (the default)
+        returns them mutable, and "error" raises a ValueError before casting any rows.
+    :param reuse_buffers: If False, table selects build every row from a fresh dict of field
+        values, even where one dict could safely be refilled for each row. Results are the
+        same either way, so this is only useful for measuring the difference.
+
+    """
+    cdef bint strict
+    cdef ValuePolicy missing_policy
+    cdef ValuePolicy null_policy
+    cdef object on_missing
+    cdef bint warn_on_missing
+    cdef set warned_keys         # Missing keys that already warned during this call
+    cdef object json_loader
+    cdef object msgpack_loader
+    cdef bint batch_json
+    cdef bint validate
+    cdef OutputMode output_mode
+    cdef dict key_prefixes
+    cdef RowFormat row_format
+    cdef object row_type         # namedtuple for multi-select rows, created on first use
+    cdef dict transforms
+    cdef frozenset non_null_fields
+    cdef dict codecs
+    cdef tuple money_format      # Symbols and separators for is_money, None for the default
+    cdef tuple numeric_format    # Separators for Decimal and float text, None for the default
+    cdef dict rename
+    cdef object row_factory
+    cdef object binary_as_memoryview
+    cdef DuplicatePolicy on_duplicate
+    cdef bint flatten_tables     # Columnar output, where table fields share the top-level keys
+    cdef bint prefix_table_keys
+    cdef bint construct_positional
+    cdef bint dedup_by_pk
+    cdef bint lazy
+    cdef bint reuse_buffers
+    cdef bint freeze
+    cdef bint freeze_required    # Tables that can't be frozen fail planning instead
+    cdef bint collect_timings
+    cdef bint track_provenance
+    cdef bint case_insensitive
+    cdef object row_class        # Type of the rows that already passed check_row
+    cdef object folded_row       # Row that folded_keys was built from
+    cdef dict folded_keys        # Lowercased key to the row's original key
+    cdef long long json_parse_ns
+    cdef long long construct_ns
+    cdef long long column_extract_ns
+
+    def __init__(
+        self,
+        *,
+        bint strict=True,
+        str missing_policy=None,
+        str null_policy="none",
+        object on_missing=None,
+        bint warn_on_missing=False,
+        object json_loads=None,
+        object unpackb=None,
+        bint json_strict=False,
+        bint batch_json=False,
+        bint validate=True,
+        str output_mode="model",
+        dict key_prefixes=None,
+        str row_format="dict",
+        dict transforms=None,
+        object non_null_fields=None,
+        bint collect_timings=False,
+        bint track_provenance=False,
+        bint case_insensitive=False,
+        dict codecs=None,
+        object money_symbols=None,
+        object money_separators=None,
+        object numeric_separators=None,
+        str construct_style="keyword",
+        bint dedup_by_pk=False,
+        bint lazy=False,
+        bint reuse_buffers=True,
+        bint freeze=False,
+        str on_unfreezable="ignore",
+        dict rename=None,
+        str on_duplicate=None,
+        bint prefix_table_keys=False,
+        object row_factory=None,
+        object binary_as_memoryview=None,
+    ):
+        if missing_policy is None:
+            missing_policy = "raise" if strict else "default"
+        if missing_policy not in VALUE_POLICIES:
+            raise ValueError(
+                f"Unknown missing_policy '{missing_policy}', expected one of {list(VALUE_POLICIES)}"
+            )
+        if null_policy not in VALUE_POLICIES:
+            raise ValueError(
+                f"Unknown null_policy '{null_policy}', expected one of {list(VALUE_POLICIES)}"
+            )
+        self.missing_policy = VALUE_POLICIES[missing_policy]
+        self.null_policy = VALUE_POLICIES[null_policy]
+        # The row loops only branch on strict, the policy only matters once a key is missing
+        self.strict = self.missing_policy == POLICY_RAISE
+        self.on_missing = on_missing
+        self.warn_on_missing = warn_on_missing
+        self.warned_keys = set()
+        if json_strict and json_loads is not None:
+            raise ValueError("json_strict only applies to the stdlib loader, not custom json_loads")
+        # Bind the loader once so JSON fields don't pay for a module global lookup per cell
+        if json_strict:
+            self.json_loader = partial(stdlib_json_loads, parse_constant=reject_json_constant)
+        else:
+            self.json_loader = stdlib_json_loads if json_loads is None else json_loads
+        self.msgpack_loader = unpackb
+        self.batch_json = batch_json
+        self.validate = validate
+
+        if output_mode not in OUTPUT_MODES:
+            raise ValueError(
+                f"Unknown output_mode '{output_mode}', expected one of {list(OUTPUT_MODES)}"
+            )
+        self.output_mode = OUTPUT_MODES[output_mode]
+        self.key_prefixes = {} if key_prefixes is None else key_prefixes
+
+        if row_format not in ROW_FORMATS:
+            raise ValueError(
+                f"Unknown row_format '{row_format}', expected one of {list(ROW_FORMATS)}"
+            )
+        self.row_format = ROW_FORMATS[row_format]
+        self.row_type = None
+        self.transforms = {} if transforms is None else transforms
+        self.non_null_fields = frozenset(() if non_null_fields is None else non_null_fields)
+        self.codecs = {} if codecs is None else codecs
+        self.numeric_format = None
+        if numeric_separators is not None:
+            if len(numeric_separators) != 2 or numeric_separators[0] == numeric_separators[1]:
+                raise ValueError(
+                    "numeric_separators takes two different (thousands, decimal) separators, "
+                    "like (',', '.')"
+                )
+            self.numeric_format = tuple(numeric_separators)
+            if money_separators is None:
+                money_separators = self.numeric_format
+        self.money_format = None
+        if money_symbols is not None or money_separators is not None:
+            if money_separators is not None and len(money_separators) != 2:
+                raise ValueError(
+                    "money_separators takes a (thousands, decimal) pair, like (',', '.')"
+                )
+            self.money_format = (
+                DEFAULT_MONEY_FORMAT[0] if money_symbols is None else tuple(money_symbols),
+                *(DEFAULT_MONEY_FORMAT[1:] if money_separators is None else money_separators),
+            )
+        self.dedup_by_pk = dedup_by_pk
+        self.lazy = lazy
+        self.reuse_buffers = reuse_buffers
+        self.rename = {} if rename is None else rename
+
+        self.binary_as_memoryview = binary_as_memoryview
+        self.row_factory = row_factory
+        if row_factory is not None:
+            if self.output_mode == OUTPUT_NAMEDTUPLE:
+                raise ValueError("row_factory can't be combined with the namedtuple output mode")
+            self.output_mode = OUTPUT_DICT
+
+        if on_duplicate is None:
+            on_duplicate = "error" if output_mode == "merged_dict" else "last"
+        if on_duplicate not in DUPLICATE_POLICIES:
+            raise ValueError(
+                f"Unknown on_duplicate '{on_duplicate}', expected one of {list(DUPLICATE_POLICIES)}"
+            )
+        self.on_duplicate = DUPLICATE_POLICIES[on_duplicate]
+        if lazy and self.output_mode != OUTPUT_MODEL:
+            raise ValueError("lazy only applies to the model output mode")
+        if freeze and self.output_mode != OUTPUT_MODEL:
+            raise ValueError("freeze only applies to the model output mode")
+        if on_unfreezable not in ("ignore", "error"):
+            raise ValueError(
+                f"Unknown on_unfreezable '{on_unfreezable}', expected one of ['ignore', 'error']"
+            )
+        if construct_style not in ("keyword", "positional"):
+            raise ValueError(
+                f"Unknown construct_style '{construct_style}', expected one of "
+                "['keyword', 'positional']"
+            )
+        self.construct_positional = construct_style == "positional"
+        if self.construct_positional and (
+            self.output_mode != OUTPUT_MODEL or lazy or not validate
+        ):
+            raise ValueError(
+                "construct_style='positional' only applies to the validating model output mode"
+            )
+        self.freeze = freeze
+        self.freeze_required = on_unfreezable == "error"
+        self.flatten_tables = output_mode == "merged_dict"
+        self.prefix_table_keys = prefix_table_keys
+        self.collect_timings = collect_timings
+        self.json_parse_ns = 0
+        self.construct_ns = 0
+        self.column_extract_ns = 0
+        self.track_provenance = track_provenance
+        self.case_insensitive = case_insensitive
+        self.row_class = None
+        self.folded_row = None
+        self.folded_keys = None
+
+    cdef object reset_call_state(self):
+        # Prepared selects reuse their options, but every batch warns and times on its own
+        self.warned_keys = set()
+        self.folded_row = None
+        self.folded_keys = None
+        self.json_parse_ns = 0
+        self.construct_ns = 0
+        self.column_extract_ns = 0
+
+cdef object apply_transforms(list plans, dict transforms):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                field.transform = transforms.get(f"{plan.output_name}_{field.name}")
+        elif plan.kind == SELECT_COLUMN:
+            plan.column.transform = transforms.get(
+                f"{plan.select_raw.root_model.get_table_name()}_{plan.select_raw.key}"
+            )
+        elif plan.kind != SELECT_UNKNOWN:
+            plan.column.transform = transforms.get(plan.column.name)
+
+cdef object apply_non_null_fields(list plans, frozenset names):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef set matched = set()
+    cdef str key
+
+    # Keyed exactly like transforms, by the names the selects have before any renaming
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                key = f"{plan.output_name}_{field.name}"
+                if key in names:
+                    field.non_null = True
+                    matched.add(key)
+        elif plan.kind == SELECT_COLUMN:
+            key = f"{plan.select_raw.root_model.get_table_name()}_{plan.select_raw.key}"
+            if key in names:
+                plan.column.non_null = True
+                matched.add(key)
+        elif plan.kind != SELECT_UNKNOWN and plan.column.name in names:
+            plan.column.non_null = True
+            matched.add(plan.column.name)
+
+    if len(matched) != len(names):
+        raise ValueError(
+            f"non_null_fields {sorted(names - matched)} don't match any selected field"
+        )
+
+cdef object require_non_null(list plans):
+    # null_policy="raise" is the same check as listing every select in non_null_fields
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                field.non_null = True
+        elif plan.kind != SELECT_UNKNOWN:
+            plan.column.non_null = True
+
+cdef object raise_null_value(FieldPlan field, Py_ssize_t row_index):
+    raise ValueError(
+        f"NULL value for non-null field '{field.name}' in row {row_index} "
+        f"(select index {field.select_index})"
+    )
+
+cdef object apply_codec(FieldPlan field, dict codecs):
+    cdef object decoder
+    if field.codec_tag is None or field.is_json:
+        return None
+    decoder = codecs.get(field.codec_tag)
+    if decoder is not None:
+        field.codec = CODEC_CUSTOM
+        field.target_type = decoder
+
+cdef object apply_codecs(list plans, dict codecs):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                apply_codec(field, codecs)
+        elif plan.kind != SELECT_UNKNOWN:
+            apply_codec(plan.column, codecs)
+
+cdef object apply_money_format(list plans, tuple money_format):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                if field.codec == CODEC_MONEY:
+                    field.target_type = money_format
+        elif plan.kind != SELECT_UNKNOWN and plan.column.codec == CODEC_MONEY:
+            plan.column.target_type = money_format
+
+cdef object apply_numeric_format(list plans, tuple numeric_format):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                if field.codec == CODEC_DECIMAL or field.codec == CODEC_FLOAT:
+                    field.target_type = numeric_format
+        elif plan.kind != SELECT_UNKNOWN and (
+            plan.column.codec == CODEC_DECIMAL or plan.column.codec == CODEC_FLOAT
+        ):
+            plan.column.target_type = numeric_format
+
+cdef object apply_binary_codec(FieldPlan field, Codec codec):
+    # Registered codecs take precedence, and JSON payloads are always decoded
+    if field.codec == CODEC_NONE and not field.is_json and field.target_type is bytes:
+        field.codec = codec
+
+cdef object apply_binary_codecs(list plans, Codec codec):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                apply_binary_codec(field, codec)
+        elif plan.kind != SELECT_UNKNOWN:
+            apply_binary_codec(plan.column, codec)
+
+cdef list primary_key_fields(SelectPlan plan):
+    # Every field flagged as a primary key, so composite keys are identified by all of them
+    cdef dict client_fields = model_field_infos(plan.select_raw)
+    # Comprehension variables aren't typed, so the plan's slots need a cast to be read
+    return [
+        field
+        for field in plan.fields
+        if client_fields[(<FieldPlan>field).name].primary_key
+    ]
+
+cdef object apply_primary_keys(list plans):
+    cdef SelectPlan plan
+
+    for plan in plans:
+        if plan.kind != SELECT_TABLE:
+            continue
+        plan.pk_fields = primary_key_fields(plan)
+        if plan.pk_fields:
+            plan.instances = {}
+
+cdef object apply_rename(list plans, dict rename):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_UNKNOWN:
+            continue
+        plan.output_name = rename.get(plan.output_name, plan.output_name)
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                field.output_key = rename.get(field.name, field.name)
+
+cdef object apply_table_key_prefixes(list plans):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                field.output_key = f"{plan.output_name}_{field.output_key}"
+
+cdef object resolve_duplicate_keys(list plans, CastingOptions options):
+    """
+    Raise on, or number, the output keys that more than one select would write to.
+
+    """
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef list owners = []        # SelectPlan or FieldPlan behind each output key, in order
+    cdef list keys = []
+    cdef list select_indexes = []
+    cdef set seen = set()
+    cdef set taken
+    cdef str key
+    cdef str candidate
+    cdef Py_ssize_t i, select_index, suffix
+
+    # A lone table is emitted as its own dict, so there are no top-level keys to collide
+    if (
+        not options.flatten_tables
+        and len(plans) == 1
+        and (<SelectPlan>plans[0]).kind == SELECT_TABLE
+    ):
+        return None
+
+    for select_index, plan in enumerate(plans):
+        if plan.kind == SELECT_UNKNOWN:
+            continue
+        if plan.kind == SELECT_TABLE and options.flatten_tables:
+            for field in plan.fields:
+                owners.append(field)
+                keys.append(field.output_key)
+                select_indexes.append(select_index)
+        else:
+            owners.append(plan)
+            keys.append(plan.output_name)
+            select_indexes.append(select_index)
+
+    taken = set(keys)
+    for i in range(len(keys)):
+        key = keys[i]
+        if key not in seen:
+            seen.add(key)
+            continue
+        if options.on_duplicate == DUPLICATE_ERROR:
+            raise ValueError(
+                f"Duplicate output key '{key}' (select index {select_indexes[i]})"
+            )
+
+        # Skip suffixes that another select already uses as its own key
+        suffix = 1
+        candidate = f"{key}_{suffix}"
+        while candidate in taken:
+            suffix += 1
+            candidate = f"{key}_{suffix}"
+        taken.add(candidate)
+        seen.add(candidate)
+        if isinstance(owners[i], FieldPlan):
+            (<FieldPlan>owners[i]).output_key = candidate
+        else:
+            (<SelectPlan>owners[i]).output_name = candidate
+
+cdef object apply_kwargs_buffers(list plans):
+    """
+    Let table selects fill the same dict for every row instead of allocating a new one. This
+    is only safe when the dict never outlives its row, and when every row writes the same
+    keys so nothing stale is left behind:
+
+    - Models and namedtuples are built with `**obj_dict`, which hands the constructor a copy.
+      Dict output and typed dicts return the dict itself, so they never reuse one.
+    - Strict mode writes every field or raises. Lenient mode can skip missing fields, and
+      the "default" and "skip" null policies can skip NULL ones.
+    - Unmapped columns for `extra="allow"` models differ from row to row.
+    - Lazy models hold on to their dict until they're first read, so they never reuse one.
+
+    Grouped casting keeps the parent's dict across rows, so it turns the buffer back off.
+
+    """
+    cdef SelectPlan plan
+
+    for plan in plans:
+        if (
+            plan.kind == SELECT_TABLE
+            and plan.extra_prefix is None
+            and not is_typed_dict(plan.select_raw)
+        ):
+            plan.kwargs_buffer = {}
+
+cdef object check_msgpack_fields(list plans):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind != SELECT_TABLE:
+            continue
+        for field in plan.fields:
+            if field.is_msgpack:
+                raise ValueError(
+                    f"Field '{field.name}' of {plan.output_name} is stored as msgpack, so "
+                    "casting it requires an unpackb callable, like msgpack.unpackb"
+                )
+
+cdef list plan_select(list select_raws, list select_types, CastingOptions options):
+    cdef list plans = build_select_plans(select_raws, select_types, options.key_prefixes)
+    if options.transforms:
+        apply_transforms(plans, options.transforms)
+    if options.non_null_fields:
+        apply_non_null_fields(plans, options.non_null_fields)
+    if options.null_policy == POLICY_RAISE:
+        require_non_null(plans)
+    if options.codecs:
+        apply_codecs(plans, options.codecs)
+    if options.money_format is not None:
+        apply_money_format(plans, options.money_format)
+    if options.numeric_format is not None:
+        apply_numeric_format(plans, options.numeric_format)
+    if options.dedup_by_pk:
+        apply_primary_keys(plans)
+    if options.msgpack_loader is None:
+        check_msgpack_fields(plans)
+    if options.binary_as_memoryview is not None:
+        apply_binary_codecs(plans, CODEC_MEMORYVIEW if options.binary_as_memoryview else CODEC_BYTES)
+    # Runs last, since transforms are keyed by the original table name
+    if options.rename and options.output_mode == OUTPUT_DICT:
+        apply_rename(plans, options.rename)
+    if options.prefix_table_keys and options.flatten_tables:
+        apply_table_key_prefixes(plans)
+    if options.on_duplicate != DUPLICATE_LAST and options.output_mode == OUTPUT_DICT:
+        resolve_duplicate_keys(plans, options)
+    if (
+        options.reuse_buffers
+        and options.strict
+        and (options.null_policy == POLICY_NONE or options.null_policy == POLICY_RAISE)
+        and options.output_mode != OUTPUT_DICT
+        and not options.lazy
+    ):
+        apply_kwargs_buffers(plans)
+    if options.freeze:
+        apply_freeze(plans, options)
+    return plans
+
+cdef bint model_is_frozen(object model):
+    if is_typed_dict(model):
+        return False
+    if is_legacy_model(model):
+        return getattr(model.__config__, "frozen", False)
+    return model.model_config.get("frozen", False)
+
+cdef object apply_freeze(list plans, CastingOptions options):
+    cdef SelectPlan plan
+
+    for plan in plans:
+        if plan.kind != SELECT_TABLE or model_is_frozen(plan.select_raw):
+            continue
+        # Tables run their modification callbacks ahead of every field assignment, which is
+        # the only per-instance hook we have without swapping in a frozen subclass
+        if isclass(plan.select_raw) and issubclass(plan.select_raw, TableBase):
+            plan.freeze = True
+        elif options.freeze_required:
+            raise ValueError(
+                f"Can't freeze {plan.select_raw.__name__} objects, since it's neither a "
+                "TableBase nor a model configured with frozen=True"
+            )
+
+def reject_modification(object instance):
+    # The assignment never lands, so it mustn't be left behind as a pending update either
+    instance.clear_modified_attributes()
+    raise FrozenModelError(
+        f"{type(instance).__name__} was cast with freeze=True and can't be modified"
+    )
+
+cdef object freeze_instance(object instance):
+    instance.register_modified_callback(reject_modification)
+    return None
+
+cdef object fetch_attribute(object value, FieldPlan field):
+    cdef str key
+
+    try:
+        return getattr(value, field.select_attribute)
+    except AttributeError:
+        pass
+    for key in field.fallback_attributes:
+        try:
+            return getattr(value, key)
+        except AttributeError:
+            pass
+    # Surface missing attributes like a missing key, so strict and lenient modes still apply
+    raise KeyError(field.select_attribute)
+
+cdef object fetch_value(object value, FieldPlan field, CastingOptions options):
+    # Subscript rather than `.get()`, so a column that's present with a SQL NULL comes back as
+    # None while a key that's absent from the row raises KeyError for the caller to handle
+    cdef str key
+
+    if options.row_format == ROW_TUPLE:
+        try:
+            return value[field.column_index]
+        except IndexError:
+            # Surface short rows like a missing key, so strict and lenient modes still apply
+            raise KeyError(field.select_attribute) from None
+
+    if options.row_format == ROW_ATTR:
+        return fetch_attribute(value, field)
+
+    try:
+        return value[field.select_attribute]
+    except KeyError:
+        if not field.fallback_attributes and not options.case_insensitive:
+            raise
+    for key in field.fallback_attributes:
+        try:
+            return value[key]
+        except KeyError:
+            pass
+
+    # Exact matches always win, so only fold the row's keys once one of them misses
+    if options.case_insensitive:
+        if options.folded_row is not value:
+            options.folded_row = value
+            options.folded_keys = {}
+            for key in value.keys():
+                options.folded_keys.setdefault(key.lower(), key)
+        key = options.folded_keys.get(field.select_attribute.lower())
+        if key is not None:
+            return value[key]
+        for key in field.fallback_attributes:
+            key = options.folded_keys.get(key.lower())
+            if key is not None:
+                return value[key]
+
+    raise KeyError(field.select_attribute)
+
+cdef object warn_missing(FieldPlan field, Py_ssize_t row_index, CastingOptions options):
+    # Deduplicated by key, so a column that's absent from every row only warns on the first
+    if field.select_attribute in options.warned_keys:
+        return None
+    options.warned_keys.add(field.select_attribute)
+    warn(
+        f"Key '{field.select_attribute}' for '{field.name}' not found in row {row_index} "
+        f"(select index {field.select_index}).",
+        MissingColumnWarning,
+    )
+    return None
+
+cdef object lookup_value(object value, FieldPlan column, Py_ssize_t row_index, CastingOptions options):
+    """
+    Fetch a single select key from the row. Missing keys either raise or resolve to None, so
+    the result tuple always keeps one slot per select.
+
+    """
+    cdef object item
+    try:
+        item = fetch_value(value, column, options)
+    except KeyError as e:
+        if options.strict:
+            raise KeyError(
+                f"Key '{column.select_attribute}' not found in row {row_index} "
+                f"(select index {column.select_index})."
+            ) from e
+        if options.warn_on_missing:
+            warn_missing(column, row_index, options)
+        if options.on_missing is not None:
+            item = options.on_missing(column.name, row_index)
+            if item is not SKIP_MISSING:
+                return item
+        return None
+
+    if item is None and column.non_null:
+        raise_null_value(column, row_index)
+    if column.codec != CODEC_NONE:
+        item = coerce_value(column, item, row_index)
+    if column.transform is not None:
+        return column.transform(item)
+    return item
+
+# Python types that a JSON decoder produces, which drivers with a JSON codec return as-is.
+# bool is covered by int.
+cdef tuple JSON_DECODED_TYPES = (dict, list, int, float)
+
+cdef object load_json_value(
+    FieldPlan field,
+    object payload,
+    Py_ssize_t row_index,
+    CastingOptions options,
 ):
+    cdef str text
+
+    if isinstance(payload, str):
+        return options.json_loader(payload)
+    # Binary JSONB payloads can come back as bytes or a memoryview, which json.loads won't
+    # take in every form. Decode straight from the buffer so memoryviews aren't copied twice.
+    if isinstance(payload, (bytes, bytearray, memoryview)):
+        try:
+            text = str(payload, "utf-8")
+        except UnicodeDecodeError as e:
+            raise ValueError(
+                f"Invalid JSON for '{field.name}' in row {row_index} "
+                f"(select index {field.select_index}): invalid UTF-8 at byte {e.start}"
+            ) from e
+        return options.json_loader(text)
+    # Drivers with a registered JSON codec hand us the decoded value already
+    if isinstance(payload, JSON_DECODED_TYPES):
+        return payload
+    raise ValueError(
+        f"Invalid JSON for '{field.name}' in row {row_index} "
+        f"(select index {field.select_index}): expected JSON text, got {type(payload).__name__}"
+    )
+
+cdef object decode_nested_json(object parsed, CastingOptions options):
+    """
+    Second pass for double-encoded JSON, where the payload decoded into a string of more
+    JSON text. Only strings that start like an object or array are parsed again, since
+    plain strings are valid JSON values too. A string that doesn't parse is left as is,
+    and a third level of encoding comes back as a string, so decoding always stops.
+
+    """
+    if not isinstance(parsed, str) or not (<str>parsed).lstrip().startswith(("{", "[")):
+        return parsed
+    try:
+        return options.json_loader(parsed)
+    except ValueError:
+        return parsed
+
+cdef object load_msgpack_value(object payload, CastingOptions options):
+    if isinstance(payload, (bytes, bytearray, memoryview)):
+        # unpackb reads straight from the buffer, so memoryviews aren't copied first
+        return options.msgpack_loader(payload)
+    return payload
+
+cdef object extract_json_path(tuple path, object payload):
+    # Anything missing along the way, or a step into something other than an object, means
+    # there's no value to extract
+    for key in path:
+        if not isinstance(payload, dict):
+            return None
+        payload = (<dict>payload).get(key)
+    return payload
+
+cdef object build_submodel(object model, object payload, CastingOptions options):
+    # Anything other than a JSON object is left for the parent model to accept or reject
+    if not isinstance(payload, dict):
+        return payload
+    if options.validate:
+        return model(**payload)
+    return construct_model(model, payload)
+
+cdef object hydrate_submodel(FieldPlan field, object payload, CastingOptions options):
+    if field.submodel_many:
+        if not isinstance(payload, list):
+            return payload
+        return [build_submodel(field.submodel, item, options) for item in payload]
+    return build_submodel(field.submodel, payload, options)
+
+cdef object apply_coercions(
+    FieldPlan field,
+    object field_value,
+    Py_ssize_t row_index,
+    CastingOptions options,
+):
+    """
+    Run the coercion steps of a JSON field in order. Only the first step decodes with
+    `unpackb` for msgpack fields, or reads the batch-parsed payload, so later decode steps
+    parse JSON text nested within the payload. Steps stop once the value is None.
+
+    """
+    cdef Py_ssize_t i
+    cdef Coercion step
+
+    for i in range(len(field.coercions)):
+        if field_value is None:
+            return None
+        step = field.coercions[i]
+        if step == COERCE_DECODE:
+            if i == 0 and field.batch_values is not None:
+                field_value = field.batch_values[row_index]
+            elif i == 0 and field.is_msgpack:
+                field_value = load_msgpack_value(field_value, options)
+            else:
+                field_value = load_json_value(field, field_value, row_index, options)
+            if field.double_decode:
+                field_value = decode_nested_json(field_value, options)
+        elif step == COERCE_PATH:
+            field_value = extract_json_path(field.json_path, field_value)
+        elif step == COERCE_SUBMODEL:
+            field_value = hydrate_submodel(field, field_value, options)
+        else:
+            field_value = coerce_value(field, field_value, row_index)
+    return field_value
+
+cdef dict collect_table_fields(
+    object value,
+    SelectPlan plan,
+    Py_ssize_t row_index,
+    CastingOptions options,
+):
+    """
+    Gather the converted field values of a table select, or None if every field is null.
+
+    """
+    cdef dict obj_dict = {} if plan.kwargs_buffer is None else plan.kwargs_buffer
+    cdef bint all_none = True
+    cdef FieldPlan field
+    cdef object field_value
+    cdef long long started = 0
+    cdef FieldPlan null_field = None
+    # Only the validating constructor resolves aliases, model_construct and dicts use field names
+    cdef bint use_init_names = options.validate and options.output_mode == OUTPUT_MODEL
+
+    # First pass: collect all fields and check if they're all None
+    for field in plan.fields:
+        try:
+            field_value = fetch_value(value, field, options)
+        except KeyError as e:
+            if field.optional_key:
+                continue
+            if options.strict:
+                raise KeyError(
+                    f"Key '{field.select_attribute}' for field '{field.name}' not found in row "
+                    f"{row_index} (select index {field.select_index})."
+                ) from e
+            if options.warn_on_missing:
+                warn_missing(field, row_index, options)
+            if options.on_missing is not None:
+                field_value = options.on_missing(field.name, row_index)
+                if field_value is not SKIP_MISSING:
+                    obj_dict[field.init_name if use_init_names else field.output_key] = field_value
+                    continue
+            if options.missing_policy == POLICY_SKIP:
+                continue
+            if options.missing_policy == POLICY_NONE:
+                obj_dict[field.init_name if use_init_names else field.output_key] = None
+                continue
+            # JSON defaults aren't declared to pydantic, so every output mode needs them passed
+            if field.default_json is not None:
+                obj_dict[field.init_name if use_init_names else field.output_key] = deepcopy(
+                    field.default_json
+                )
+                continue
+            # The validating constructor applies defaults itself, and passing them explicitly
+            # would validate defaults like `id: int = None` that pydantic normally leaves
+            # alone. Everywhere else we fill them in, calling factories once per row so
+            # mutable defaults aren't shared.
+            if use_init_names:
+                continue
+            if field.default_factory is not None:
+                obj_dict[field.output_key] = field.default_factory()
+            elif field.default is not PydanticUndefined:
+                obj_dict[field.output_key] = field.default
+            continue
+
+        if field_value is not None:
+            all_none = False
+            # JSON still has to be parsed up front, since model_construct won't coerce it for us
+            if field.is_json:
+                if options.collect_timings:
+                    started = perf_counter_ns()
+                field_value = apply_coercions(field, field_value, row_index, options)
+                if options.collect_timings:
+                    options.json_parse_ns += perf_counter_ns() - started
+            elif field.codec != CODEC_NONE:
+                field_value = coerce_value(field, field_value, row_index)
+        elif field.non_null:
+            # Only a violation once we know the table has a value at all
+            if null_field is None:
+                null_field = field
+        elif options.null_policy == POLICY_SKIP:
+            continue
+        elif options.null_policy == POLICY_DEFAULT and field.default_json is None:
+            # Like a missing field, the validating constructor fills in declared defaults itself
+            if field.default_factory is not None or field.default is not PydanticUndefined:
+                if use_init_names:
+                    continue
+                if field.default_factory is not None:
+                    field_value = field.default_factory()
+                else:
+                    field_value = field.default
+
+        # Stands in for JSON that's NULL in the database as well as JSON null, but doesn't
+        # count as a value, so rows where every field is NULL still collapse to None
+        if field_value is None and field.default_json is not None:
+            field_value = deepcopy(field.default_json)
+
+        if field.transform is not None:
+            field_value = field.transform(field_value)
+
+        obj_dict[field.init_name if use_init_names else field.output_key] = field_value
+
+    # If all fields are None, store None instead of creating the table object
+    if all_none:
+        return None
+    if null_field is not None:
+        raise_null_value(null_field, row_index)
+
+    # Positional rows carry no keys to pass through, and namedtuples only hold declared fields
+    if (
+        plan.extra_prefix is not None
+        and options.row_format == ROW_DICT
+        and options.output_mode != OUTPUT_NAMEDTUPLE
+    ):
+        collect_extra_columns(value, plan, obj_dict)
+    return obj_dict
+
+cdef object construct_positional(SelectPlan plan, dict obj_dict):
+    cdef list args = []
+    cdef dict extras
+    cdef FieldPlan field
+
+    for field in plan.fields:
+        if field.init_name in obj_dict:
+            args.append(obj_dict[field.init_name])
+        elif field.default_factory is not None:
+            args.append(field.default_factory())
+        elif field.default is not PydanticUndefined:
+            args.append(field.default)
+        else:
+            args.append(None)
+
+    if plan.extra_prefix is None:
+        return plan.select_raw(*args)
+    # Everything that isn't a declared field is an unmapped column
+    extras = dict(obj_dict)
+    for field in plan.fields:
+        extras.pop(field.init_name, None)
+    return plan.select_raw(*args, **extras)
+
+cdef object construct_table(SelectPlan plan, dict obj_dict, CastingOptions options):
+    cdef object instance
+
+    if options.output_mode == OUTPUT_DICT:
+        return obj_dict
+
+    if options.output_mode == OUTPUT_NAMEDTUPLE:
+        if plan.row_type is None:
+            # Fields missing from a lenient row default to None instead of failing construction
+            plan.row_type = namedtuple(
+                f"{plan.select_raw.__name__}Row",
+                [(<FieldPlan>field).name for field in plan.fields],
+                defaults=[None] * len(plan.fields),
+            )
+        return plan.row_type(**obj_dict)
+
+    # The declared keys are all a typed dict has, and there's nothing to construct
+    if is_typed_dict(plan.select_raw):
+        return obj_dict
+
+    if options.lazy:
+        return LazyModel(plan.select_raw, obj_dict, options.validate, plan.freeze)
+
+    if options.construct_positional:
+        instance = construct_positional(plan, obj_dict)
+    elif options.validate:
+        instance = plan.select_raw(**obj_dict)
+    else:
+        # Trust the database types and skip pydantic validation entirely
+        instance = construct_model(plan.select_raw, obj_dict)
+    if plan.freeze:
+        freeze_instance(instance)
+    return instance
+
+cdef object read_primary_key(object value, SelectPlan plan, CastingOptions options):
+    """
+    Fetch the raw primary key of a table select. Returns None when any part of it is null or
+    missing, which leaves the row to be built (or rejected) like any other.
+
+    """
+    cdef FieldPlan field
+    cdef list parts = []
+    cdef object part
+
+    for field in plan.pk_fields:
+        try:
+            part = fetch_value(value, field, options)
+        except KeyError:
+            return None
+        if part is None:
+            return None
+        parts.append(part)
+    return parts[0] if len(parts) == 1 else tuple(parts)
+
+cdef object build_table_value(
+    object value,
+    SelectPlan plan,
+    Py_ssize_t row_index,
+    CastingOptions options,
+):
+    cdef object primary_key
+    cdef object result
+
+    if plan.instances is None:
+        return cast_table_value(value, plan, row_index, options)
+
+    primary_key = read_primary_key(value, plan, options)
+    if primary_key is None:
+        return cast_table_value(value, plan, row_index, options)
+
+    result = plan.instances.get(primary_key)
+    if result is None:
+        result = cast_table_value(value, plan, row_index, options)
+        if result is not None:
+            plan.instances[primary_key] = result
+    return result
+
+cdef object cast_table_value(
+    object value,
+    SelectPlan plan,
+    Py_ssize_t row_index,
+    CastingOptions options,
+):
+    cdef dict obj_dict
+    cdef object result
+    cdef long long started
+    cdef long long json_parse_before
+
+    if not options.collect_timings:
+        obj_dict = collect_table_fields(value, plan, row_index, options)
+        if obj_dict is None:
+            return None
+        return construct_table(plan, obj_dict, options)
+
+    started = perf_counter_ns()
+    json_parse_before = options.json_parse_ns
+    obj_dict = collect_table_fields(value, plan, row_index, options)
+    # JSON parsing is reported on its own, so take it back out of the extraction time
+    options.column_extract_ns += (
+        perf_counter_ns() - started - (options.json_parse_ns - json_parse_before)
+    )
+    if obj_dict is None:
+        return None
+
+    started = perf_counter_ns()
+    result = construct_table(plan, obj_dict, options)
+    options.construct_ns += perf_counter_ns() - started
+    return result
+
+cdef object check_row(object value, Py_ssize_t row_index, CastingOptions options):
+    """
+    Reject a row that can't be read in the configured `row_format`, like a None or an int
+    that ended up in `values`, before it fails somewhere inside the lookups. Rows nearly
+    always share one type, so only the first row of each type is inspected.
+
+    """
+    cdef object row_class = type(value)
+    if row_class is options.row_class:
+        return None
+    if options.row_format == ROW_DICT:
+        if not (hasattr(row_class, "__getitem__") and hasattr(row_class, "keys")):
+            raise TypeError(f"Row {row_index} is not a mapping (got {row_class.__name__})")
+    elif options.row_format == ROW_TUPLE:
+        if not hasattr(row_class, "__getitem__") or issubclass(row_class, (str, bytes)):
+            raise TypeError(f"Row {row_index} is not a sequence (got {row_class.__name__})")
+    elif value is None:
+        # Any object can carry attributes, but None would only look like a row of missing keys
+        raise TypeError(f"Row {row_index} is None, expected a row object")
+    options.row_class = row_class
+
+cdef object note_row_position(Exception error, Py_ssize_t row_index, Py_ssize_t select_index):
+    # Our own KeyError and ValueError messages already name the row and select, so this is
+    # only for errors raised by pydantic validation, transforms, and model constructors
+    if type(error) is not KeyError and type(error) is not ValueError:
+        error.add_note(f"Raised while casting row {row_index} (select index {select_index})")
+
+cdef dict merge_row(list plans, list result_value):
+    cdef dict row = {}
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef Py_ssize_t j
+
+    for j in range(len(plans)):
+        plan = plans[j]
+        if plan.kind != SELECT_TABLE:
+            row[plan.output_name] = result_value[j]
+        elif result_value[j] is not None:
+            row.update(<dict>result_value[j])
+        else:
+            # Keep the same keys as the other rows, like a LEFT JOIN without a match
+            for field in plan.fields:
+                row[field.output_key] = None
+    return row
+
+cdef object process_row(
+    object value,
+    list plans,
+    list result_value,
+    Py_ssize_t row_index,
+    CastingOptions options,
+):
+    """
+    Materialize one row. `result_value` is scratch space with one slot per select, reused
+    across rows by the caller.
+
+    """
+    cdef Py_ssize_t j
+    cdef Py_ssize_t num_selects = len(plans)
+    cdef SelectPlan plan
+    cdef long long started
+    cdef object row
+
+    check_row(value, row_index, options)
+    for j in range(num_selects):
+        plan = plans[j]
+
+        try:
+            if plan.kind == SELECT_TABLE:
+                result_value[j] = build_table_value(value, plan, row_index, options)
+            elif plan.kind == SELECT_UNKNOWN:
+                result_value[j] = None
+            elif options.collect_timings:
+                started = perf_counter_ns()
+                result_value[j] = lookup_value(value, plan.column, row_index, options)
+                options.column_extract_ns += perf_counter_ns() - started
+            else:
+                result_value[j] = lookup_value(value, plan.column, row_index, options)
+        except Exception as e:
+            note_row_position(e, row_index, j)
+            raise
+
+    # Assemble the result
+    if options.output_mode != OUTPUT_MODEL:
+        if num_selects == 1 and (<SelectPlan>plans[0]).kind == SELECT_TABLE:
+            row = result_value[0]
+        elif options.flatten_tables:
+            row = merge_row(plans, result_value)
+        elif options.output_mode == OUTPUT_DICT:
+            row = {
+                (<SelectPlan>plans[j]).output_name: result_value[j]
+                for j in range(num_selects)
+            }
+        else:
+            if options.row_type is None:
+                # rename swaps duplicate or invalid names, like the same key from two tables,
+                # for positional ones instead of failing the whole call
+                options.row_type = namedtuple(
+                    "Row",
+                    [(<SelectPlan>plans[j]).output_name or "" for j in range(num_selects)],
+                    rename=True,
+                )
+            return options.row_type._make(result_value)
+
+        # Custom row objects are built from the same dicts that dict output returns
+        if options.row_factory is not None and row is not None:
+            return options.row_factory(row)
+        return row
+
+    if num_selects == 1:
+        return result_value[0]
+    return tuple(result_value)
+
+cdef bint is_column_select(list plans, CastingOptions options):
+    # Timed calls stay on the general path, since it's the one that attributes the extraction
+    cdef SelectPlan plan
+    if options.output_mode != OUTPUT_MODEL or options.collect_timings:
+        return False
+    for plan in plans:
+        if plan.kind != SELECT_COLUMN:
+            return False
+    return True
+
+cdef object parse_json_batch(list values, FieldPlan field, CastingOptions options):
+    """
+    Parse every payload of one JSON field with a single loader call, by joining them into
+    one JSON array. Returns the parsed payloads indexed by row, or None when the column has
+    anything other than JSON text, or when the joined array doesn't parse into one element
+    per payload. The row loop then parses those fields one row at a time as usual, which
+    also reports errors against the row that caused them.
+
+    """
+    cdef list texts = []
+    cdef list positions = []
+    cdef list batch
+    cdef object payload
+    cdef object parsed
+    cdef Py_ssize_t i
+
+    for i in range(len(values)):
+        try:
+            payload = fetch_value(values[i], field, options)
+        except (KeyError, TypeError):
+            # Malformed rows are reported by the row loop
+            continue
+        if payload is None:
+            continue
+        if not isinstance(payload, str):
+            return None
+        texts.append(payload)
+        positions.append(i)
+
+    if not texts:
+        return None
+    try:
+        parsed = options.json_loader("[" + ",".join(texts) + "]")
+    except ValueError:
+        return None
+    if not isinstance(parsed, list) or len(<list>parsed) != len(texts):
+        return None
+
+    batch = [None] * len(values)
+    for i in range(len(texts)):
+        batch[<Py_ssize_t>positions[i]] = (<list>parsed)[i]
+    return batch
+
+cdef list parse_json_batches(list values, list plans, CastingOptions options):
+    # Returns the fields that were batched, so the caller can drop their payloads afterwards
+    cdef list batched_fields = []
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef long long started = 0
+
+    if options.collect_timings:
+        started = perf_counter_ns()
+    for plan in plans:
+        if plan.kind != SELECT_TABLE:
+            continue
+        for field in plan.fields:
+            # Only stands in for a decode of the raw column, not one further down the chain
+            if (
+                field.is_json
+                and not field.is_msgpack
+                and field.coercions
+                and field.coercions[0] == COERCE_DECODE
+            ):
+                field.batch_values = parse_json_batch(values, field, options)
+                if field.batch_values is not None:
+                    batched_fields.append(field)
+    if options.collect_timings:
+        options.json_parse_ns += perf_counter_ns() - started
+    return batched_fields
+
+# Rows between checks for pending signals, like a Ctrl-C, in the row loops. The loops spend
+# most of their time in C, where Python only runs signal handlers when asked to, and checking
+# on every row would cost more than the casting saves.
+cdef Py_ssize_t SIGNAL_CHECK_INTERVAL = 1024
+
+cdef inline int check_signals(Py_ssize_t row_index) except -1:
+    # Raises whatever the handler raised, like KeyboardInterrupt, to abort the loop
+    if row_index % SIGNAL_CHECK_INTERVAL == 0:
+        return PyErr_CheckSignals()
+    return 0
+
+cdef list process_column_values(list values, list plans, CastingOptions options):
+    """
+    Row loop for selects made up only of columns, like `.select((User.id, User.name))`. Skips
+    the per-select dispatch of `process_row`, and reads columns that need no conversion
+    straight off the row. Anything else, including a missing key, goes through
+    `lookup_value`, so the results match the general path exactly.
+
+    """
     cdef Py_ssize_t num_values = len(values)
+    cdef Py_ssize_t num_selects = len(plans)
+    cdef list columns = [(<SelectPlan>plan).column for plan in plans]
+    cdef list direct = [
+        (<FieldPlan>column).codec == CODEC_NONE
+        and (<FieldPlan>column).transform is None
+        and not (<FieldPlan>column).non_null
+        for column in columns
+    ]
     cdef list result_all = [None] * num_values
-    cdef Py_ssize_t i, j, k, num_fields
-    cdef PyObject** result_value
-    cdef object value, obj, item
-    cdef dict obj_dict
-    cdef bint raw_is_table, raw_is_column, raw_is_function_metadata, raw_is_alias
-    cdef char* field_name_c
-    cdef char* select_name_c
-    cdef str field_name
-    cdef str select_name
-    cdef object field_value
-    cdef object select_raw
-    cdef PyObject* temp_obj
-    cdef bint all_none
+    cdef list result_value = [None] * num_selects
+    cdef bint keyed_rows = options.row_format == ROW_DICT
+    cdef Py_ssize_t i, j
+    cdef object value
+    cdef FieldPlan column
 
     for i in range(num_values):
+        check_signals(i)
         value = values[i]
-        result_value = <PyObject**>malloc(num_selects * sizeof(PyObject*))
-        if not result_value:
-            raise MemoryError("Failed to allocate memory for result_value.")
-        try:
-            for j in range(num_selects):
-                select_raw = select_raws[j]
-                raw_is_table, raw_is_column, raw_is_function_metadata = select_types[j]
-                raw_is_alias = isinstance(select_raw, Alias)
-
-                if raw_is_table:
-                    obj_dict = {}
-                    num_fields = num_fields_array[j]
-                    all_none = True
-
-                    # First pass: collect all fields and check if they're all None
-                    for k in range(num_fields):
-                        field_name_c = fields[j][k].name
-                        select_name_c = fields[j][k].select_attribute
-                        field_name = field_name_c.decode('utf-8')
-                        select_name = select_name_c.decode('utf-8')
-
-                        try:
-                            field_value = value[select_name]
-                        except KeyError:
-                            raise KeyError(f"Key '{select_name}' not found in value.")
-
-                        if field_value is not None:
-                            all_none = False
-                            if fields[j][k].is_json:
-                                field_value = json_loads(field_value)
-
-                        obj_dict[field_name] = field_value
-
-                    # If all fields are None, store None instead of creating the table object
-                    if all_none:
-                        result_value[j] = <PyObject*>None
-                        Py_INCREF(None)
-                    else:
-                        obj = select_raw(**obj_dict)
-                        result_value[j] = <PyObject*>obj
-                        Py_INCREF(obj)
-
-                elif raw_is_column:
-                    try:
-                        # Use the table-qualified column name
-                        table_name = select_raw.root_model.get_table_name()
-                        column_name = select_raw.key
-                        item = value[f"{table_name}_{column_name}"]
-                    except KeyError:
-                        raise KeyError(f"Key '{table_name}_{column_name}' not found in value.")
-                    result_value[j] = <PyObject*>item
-                    Py_INCREF(item)
-
-                elif raw_is_function_metadata:
-                    try:
-                        item = value[select_raw.local_name]
-                    except KeyError:
-                        raise KeyError(f"Key '{select_raw.local_name}' not found in value.")
-                    result_value[j] = <PyObject*>item
-                    Py_INCREF(item)
-
-                elif raw_is_alias:
-                    try:
-                        item = value[select_raw.name]
-                    except KeyError:
-                        raise KeyError(f"Key '{select_raw.name}' not found in value.")
-                    result_value[j] = <PyObject*>item
-                    Py_INCREF(item)
-
-            # Assemble the result
-            if num_selects == 1:
-                result_all[i] = <object>result_value[0]
-                Py_DECREF(<object>result_value[0])
-            else:
-                result_tuple = tuple([<object>result_value[j] for j in range(num_selects)])
-                for j in range(num_selects):
-                    Py_DECREF(<object>result_value[j])
-                result_all[i] = result_tuple
+        check_row(value, i, options)
+        for j in range(num_selects):
+            column = columns[j]
+            if keyed_rows and direct[j]:
+                try:
+                    result_value[j] = value[column.select_attribute]
+                    continue
+                except KeyError:
+                    pass
+            try:
+                result_value[j] = lookup_value(value, column, i, options)
+            except Exception as e:
+                note_row_position(e, i, j)
+                raise
+        result_all[i] = result_value[0] if num_selects == 1 else tuple(result_value)
+    return result_all
 
+cdef list process_values(object values, list plans, CastingOptions options):
+    # Every phase of the row loop needs the GIL: reading keys off the asyncpg Records, json.loads,
+    # and the model constructor all operate on Python objects. The only GIL-free work would be
+    # iterating the plan itself, which is already resolved up front and too cheap to justify
+    # snapshotting each row into C structures just to release the lock around it.
+    cdef Py_ssize_t num_values
+    cdef list result_all
+    cdef list result_value = [None] * len(plans)
+    cdef Py_ssize_t i = 0
+    cdef object value
+    cdef list batched_fields
+
+    if isinstance(values, list):
+        if is_column_select(plans, options):
+            return process_column_values(values, plans, options)
+
+        num_values = len(values)
+        result_all = [None] * num_values
+        batched_fields = parse_json_batches(values, plans, options) if options.batch_json else []
+        try:
+            for i in range(num_values):
+                check_signals(i)
+                result_all[i] = process_row(values[i], plans, result_value, i, options)
         finally:
-            free(result_value)
+            # Plans outlive the call when they're prepared, so don't leave the payloads behind
+            for field in batched_fields:
+                (<FieldPlan>field).batch_values = None
+        return result_all
 
+    # Cursors and generators are consumed one row at a time instead of being copied into a list
+    result_all = []
+    for value in values:
+        check_signals(i)
+        result_all.append(process_row(value, plans, result_value, i, options))
+        i += 1
     return result_all
 
-cdef list optimize_casting(list values, list select_raws, list select_types):
-    cdef Py_ssize_t num_selects = len(select_raws)
-    cdef Py_ssize_t* num_fields_array = <Py_ssize_t*>malloc(num_selects * sizeof(Py_ssize_t))
-    cdef FieldInfo** fields
+cdef Py_ssize_t process_into(object values, list plans, list out, CastingOptions options):
+    # Appending as we go means rows cast before a failing row are left in `out`
+    cdef list result_value = [None] * len(plans)
+    cdef Py_ssize_t i = 0
+    cdef object value
+
+    for value in values:
+        check_signals(i)
+        out.append(process_row(value, plans, result_value, i, options))
+        i += 1
+    return i
+
+cdef tuple process_limited(object values, list plans, Py_ssize_t limit, CastingOptions options):
     cdef list result_all
+    cdef object rows
 
-    if not num_fields_array:
-        raise MemoryError("Failed to allocate memory for num_fields_array.")
+    # Slicing a list only copies references, and keeps the column select fast path
+    if isinstance(values, list):
+        return process_values(values[:limit], plans, options), len(values) > limit
 
-    try:
-        fields = precompute_fields(select_raws, select_types, num_selects, num_fields_array)
-        result_all = process_values(values, fields, num_fields_array, select_raws, select_types, num_selects)
-    finally:
-        free_fields(fields, num_fields_array, num_selects)
+    rows = iter(values)
+    result_all = process_values(islice(rows, limit), plans, options)
+    # Peeking for more input pulls one row past the limit, which is never cast
+    for _ in rows:
+        return result_all, True
+    return result_all, False
 
+cdef list process_chunked(
+    object values,
+    list plans,
+    Py_ssize_t chunk_size,
+    object progress,
+    CastingOptions options,
+):
+    """
+    Same row loop as `process_values`, reporting `progress(rows_done, total)` after every
+    `chunk_size` rows and once more for a trailing partial chunk. `total` is None when the
+    rows come from an iterable without a length.
+
+    """
+    cdef list result_all = []
+    cdef list result_value = [None] * len(plans)
+    cdef object total = len(values) if isinstance(values, list) else None
+    cdef Py_ssize_t i = 0
+    cdef object value
+
+    for value in values:
+        check_signals(i)
+        result_all.append(process_row(value, plans, result_value, i, options))
+        i += 1
+        if progress is not None and i % chunk_size == 0:
+            progress(i, total)
+
+    if progress is not None and i % chunk_size != 0:
+        progress(i, total)
     return result_all
 
+cdef str describe_error(Exception error):
+    # KeyError wraps its message in quotes when formatted, so read the message directly
+    if isinstance(error, KeyError) and error.args:
+        return str(error.args[0])
+    return str(error)
+
+cdef tuple process_values_lenient(object values, list plans, CastingOptions options):
+    cdef list result_all = []
+    cdef list errors = []
+    cdef list result_value = [None] * len(plans)
+    cdef Py_ssize_t i = 0
+    cdef object value
+
+    for value in values:
+        check_signals(i)
+        try:
+            result_all.append(process_row(value, plans, result_value, i, options))
+        except (KeyError, ValueError, TypeError) as e:
+            errors.append((i, describe_error(e)))
+        i += 1
+
+    return result_all, errors
+
+cdef class CastingIterator:
+    """
+    Lazily materialize rows one at a time, so callers can stream through a large result set
+    without holding every hydrated object in memory at once.
+
+    """
+    cdef object rows
+    cdef list plans
+    cdef list result_value
+    cdef CastingOptions options
+    cdef Py_ssize_t row_index
+
+    def __init__(self, object values, list plans, CastingOptions options):
+        self.rows = iter(values)
+        self.plans = plans
+        self.result_value = [None] * len(plans)
+        self.options = options
+        self.row_index = 0
+
+    def __iter__(self):
+        return self
+
+    def __next__(self):
+        cdef object result = process_row(
+            next(self.rows), self.plans, self.result_value, self.row_index, self.options
+        )
+        self.row_index += 1
+        return result
+
+cdef dict process_columnar(list values, list plans, CastingOptions options):
+    """
+    Transpose the rows into one list per output column. Table selects are expanded so each of
+    their fields becomes its own column.
+
+    """
+    cdef Py_ssize_t num_values = len(values)
+    cdef dict columns = {}
+    # Parallel to `plans`: the output list for a column select, or the per-field lists of a table
+    cdef list targets = []
+    cdef list field_targets
+    cdef Py_ssize_t i, k
+    cdef object value, table_value
+    cdef SelectPlan plan
+    cdef FieldPlan field
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            field_targets = []
+            for field in plan.fields:
+                columns[field.output_key] = [None] * num_values
+                field_targets.append(columns[field.output_key])
+            targets.append(field_targets)
+        elif plan.kind == SELECT_UNKNOWN:
+            targets.append(None)
+        else:
+            columns[plan.output_name] = [None] * num_values
+            targets.append(columns[plan.output_name])
+
+    for i in range(num_values):
+        check_signals(i)
+        value = values[i]
+        check_row(value, i, options)
+        for plan, target in zip(plans, targets):
+            if plan.kind == SELECT_TABLE:
+                table_value = build_table_value(value, plan, i, options)
+                if table_value is None:
+                    continue
+                for k in range(len(plan.fields)):
+                    (<list>target[k])[i] = (<dict>table_value).get(
+                        (<FieldPlan>plan.fields[k]).output_key
+                    )
+            elif plan.kind != SELECT_UNKNOWN:
+                (<list>target)[i] = lookup_value(value, plan.column, i, options)
+
+    return columns
+
+cdef list process_grouped(
+    list values,
+    SelectPlan parent_plan,
+    SelectPlan child_plan,
+    list key_fields,
+    str relationship,
+    CastingOptions options,
+):
+    cdef list result_all = []
+    cdef dict parent_dict = None
+    cdef list children = None
+    cdef object parent_key = None
+    cdef object row_key
+    cdef object child
+    cdef object value
+    cdef FieldPlan key_field
+    cdef Py_ssize_t i
+
+    for i in range(len(values)):
+        check_signals(i)
+        value = values[i]
+        check_row(value, i, options)
+        try:
+            if len(key_fields) == 1:
+                key_field = key_fields[0]
+                row_key = fetch_value(value, key_field, options)
+            else:
+                # Composite keys compare as a whole, so rows sharing only part of one split
+                row_key = []
+                for key_field in key_fields:
+                    row_key.append(fetch_value(value, key_field, options))
+                row_key = tuple(row_key)
+        except KeyError as e:
+            raise KeyError(
+                f"Key '{key_field.select_attribute}' for field '{key_field.name}' not found in row "
+                f"{i} (select index {key_field.select_index})."
+            ) from e
+
+        # Rows are expected to arrive ordered by the parent key, so a new key closes the group
+        if parent_dict is None or row_key != parent_key:
+            if parent_dict is not None:
+                parent_dict[relationship] = children
+                result_all.append(construct_table(parent_plan, parent_dict, options))
+            parent_dict = collect_table_fields(value, parent_plan, i, options)
+            parent_key = row_key
+            children = []
+            if parent_dict is None:
+                continue
+
+        # LEFT JOINs without a match fill the child side with nulls, which leaves the list empty
+        child = build_table_value(value, child_plan, i, options)
+        if child is not None:
+            children.append(child)
+
+    if parent_dict is not None:
+        parent_dict[relationship] = children
+        result_all.append(construct_table(parent_plan, parent_dict, options))
+
+    return result_all
+
+cdef list process_scalars(object values, FieldPlan column, CastingOptions options):
+    cdef list result_all = []
+    cdef Py_ssize_t i = 0
+    cdef object value
+
+    for value in values:
+        check_signals(i)
+        check_row(value, i, options)
+        result_all.append(lookup_value(value, column, i, options))
+        i += 1
+    return result_all
+
+cdef list derive_select_types(list select_raws):
+    return [
+        (
+            is_base_table(select_raw),
+            is_column(select_raw),
+            is_function_metadata(select_raw),
+        )
+        for select_raw in select_raws
+    ]
+
+cdef str value_provenance(object value, FieldPlan field, bint fills_default, CastingOptions options):
+    try:
+        if fetch_value(value, field, options) is None:
+            return "null"
+        return "present"
+    except KeyError:
+        if fills_default and (
+            field.default_factory is not None or field.default is not PydanticUndefined
+        ):
+            return "default"
+        return "missing"
+
+cdef tuple row_provenance(object value, list plans, CastingOptions options):
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef list states = []
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            states.append({
+                field.name: value_provenance(
+                    value, field, options.missing_policy == POLICY_DEFAULT, options
+                )
+                for field in plan.fields
+            })
+        elif plan.kind == SELECT_UNKNOWN:
+            states.append("missing")
+        else:
+            # Missing columns resolve to None rather than their field's default
+            states.append(value_provenance(value, plan.column, False, options))
+    return tuple(states)
+
+cdef object optimize_casting(object values, list select_raws, list select_types, CastingOptions options):
+    cdef list plans
+    cdef long long started
+
+    if not options.collect_timings:
+        plans = plan_select(select_raws, select_types, options)
+        return process_reported(values, plans, options, 0)
+
+    started = perf_counter_ns()
+    plans = plan_select(select_raws, select_types, options)
+    return process_reported(values, plans, options, perf_counter_ns() - started)
+
+cdef object process_reported(
+    object values,
+    list plans,
+    CastingOptions options,
+    long long plan_build_ns,
+):
+    """
+    Cast every row, along with the timings and provenance that `options` asks for.
+
+    """
+    cdef list results
+    cdef list provenance
+    cdef dict timings
+
+    if not options.collect_timings and not options.track_provenance:
+        return process_values(values, plans, options)
+
+    # Provenance reads every row a second time, once casting is done
+    if options.track_provenance and not isinstance(values, list):
+        values = list(values)
+
+    results = process_values(values, plans, options)
+    if not options.collect_timings:
+        return results, [row_provenance(value, plans, options) for value in values]
+
+    timings = {
+        "plan_build": plan_build_ns,
+        "json_parse": options.json_parse_ns,
+        "construct": options.construct_ns,
+        "column_extract": options.column_extract_ns,
+    }
+    if not options.track_provenance:
+        return results, timings
+    provenance = [row_provenance(value, plans, options) for value in values]
+    return results, timings, provenance
+
+cdef CastingOptions entrypoint_options(str entrypoint, dict options):
+    # Only optimize_casting and PreparedSelect return anything next to the results, so the
+    # other entrypoints refuse these options instead of quietly dropping them
+    if options.get("collect_timings"):
+        raise ValueError(
+            f"{entrypoint} doesn't return timings, collect_timings is only supported by "
+            "optimize_exec_casting, optimize_exec_auto, and PreparedSelect"
+        )
+    if options.get("track_provenance"):
+        raise ValueError(
+            f"{entrypoint} doesn't return provenance, track_provenance is only supported by "
+            "optimize_exec_casting, optimize_exec_auto, and PreparedSelect"
+        )
+    return CastingOptions(**options)
+
 def optimize_exec_casting(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Union[List[Any], Tuple[Any, ...]]:
+    """
+    Cast raw database rows into the objects requested by the select. `values` can be a list or
+    any iterable of rows, like a server-side cursor, which is consumed one row at a time. Keyword
+    options are described on `CastingOptions`.
+
+    """
+    return optimize_casting(values, select_raws, select_types, CastingOptions(**options))
+
+def optimize_exec_lenient(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Tuple[List[Any], List[Tuple[int, str]]]:
+    """
+    Version of `optimize_exec_casting` that keeps going past rows that fail to cast. Returns the
+    successfully cast rows along with a `(row_index, error_message)` entry for every row that
+    raised a KeyError, ValueError (including pydantic validation errors), or TypeError. Accepts
+    the same options as `optimize_exec_casting`, apart from `collect_timings` and
+    `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_lenient", options)
+    return process_values_lenient(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        casting_options,
+    )
+
+def optimize_exec_auto(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    **options: Any,
+) -> Union[List[Any], Tuple[Any, ...]]:
+    """
+    Version of `optimize_exec_casting` that reads the table, column, and function flags off the
+    select objects itself, so they can't drift out of sync with `select_raws`. The flags are
+    derived once per call, ahead of the row loop. Accepts the same options as
+    `optimize_exec_casting`.
+
+    """
+    return optimize_casting(
+        values,
+        select_raws,
+        derive_select_types(select_raws),
+        CastingOptions(**options),
+    )
+
+def optimize_exec_scalar(
+    values: Iterable[Any],
+    select_raw: Any,
+    select_type: Tuple[bool, bool, bool],
+    **options: Any,
+) -> List[Any]:
+    """
+    Cast the single column of a scalar query, like `SELECT COUNT(*)`, into a flat list of
+    values. `select_raw` must be a column, function, or alias select. Accepts the same options
+    as `optimize_exec_casting`, apart from `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_scalar", options)
+    cdef SelectPlan plan = plan_select([select_raw], [select_type], casting_options)[0]
+
+    if plan.kind == SELECT_TABLE:
+        raise ValueError(
+            f"Scalar casting requires a column, function, or alias select, not table {plan.output_name}"
+        )
+    if plan.kind == SELECT_UNKNOWN:
+        raise ValueError(f"Unsupported select for scalar casting: {select_raw!r}")
+
+    return process_scalars(values, plan.column, casting_options)
+
+def optimize_exec_single(
+    value: Any,
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Any:
+    """
+    Cast a single raw database row, returning the materialized object (or tuple of objects)
+    directly instead of a one-element list. Accepts the same options as `optimize_exec_casting`,
+    apart from `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_single", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    return process_row(value, plans, [None] * len(plans), 0, casting_options)
+
+def optimize_exec_one(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Any:
+    """
+    Cast the only row of a query, for `.one()` style lookups. Raises `NoRowsError` if `values`
+    is empty and `MultipleRowsError` if it holds more than one row. Only the first two rows are
+    ever pulled from `values`. Accepts the same options as `optimize_exec_casting`, apart from
+    `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_one", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    cdef object rows = iter(values)
+    cdef object value
+
+    try:
+        value = next(rows)
+    except StopIteration:
+        raise NoRowsError("Expected exactly one row, but the query returned none") from None
+
+    for _ in rows:
+        raise MultipleRowsError("Expected exactly one row, but the query returned several")
+
+    return process_row(value, plans, [None] * len(plans), 0, casting_options)
+
+def optimize_exec_first(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Any:
+    """
+    Cast the first row of a query, for `.first()` style lookups, or return None if `values` is
+    empty. Only the first row is ever pulled from `values`, and the rest are ignored. Accepts
+    the same options as `optimize_exec_casting`, apart from `collect_timings` and
+    `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_first", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+
+    for value in values:
+        return process_row(value, plans, [None] * len(plans), 0, casting_options)
+    return None
+
+def optimize_exec_into(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    out: List[Any],
+    **options: Any,
+) -> int:
+    """
+    Cast every row like `optimize_exec_casting`, appending them to `out` in place instead of
+    returning a new list, and return how many rows were appended. Lets paginated loops build up
+    a single accumulator across pages. Rows cast before a failing row stay in `out`. Accepts the
+    same options as `optimize_exec_casting`, apart from `collect_timings` and
+    `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_into", options)
+    return process_into(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        out,
+        casting_options,
+    )
+
+def optimize_exec_limited(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    limit: int,
+    **options: Any,
+) -> Tuple[List[Any], bool]:
+    """
+    Cast only the first `limit` rows, for queries that over-fetch to find out whether there's
+    another page. Returns the cast rows along with whether `values` held any rows past the
+    limit, which are never cast. For iterables other than lists, one extra row is pulled to
+    check. Accepts the same options as `optimize_exec_casting`, apart from `collect_timings` and
+    `track_provenance`.
+
+    """
+    if limit < 0:
+        raise ValueError(f"limit can't be negative, got {limit}")
+
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_limited", options)
+    return process_limited(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        limit,
+        casting_options,
+    )
+
+cdef list select_schema(list plans):
+    cdef list schema = []
+    cdef SelectPlan plan
+    cdef object output_type
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            output_type = plan.select_raw
+        elif plan.kind == SELECT_COLUMN:
+            output_type = plan.select_raw.field_definition.annotation
+        elif plan.kind == SELECT_ALIAS:
+            output_type = plan.select_raw.type
+        elif plan.kind == SELECT_FUNCTION:
+            # Function metadata only carries its result type for static type checkers
+            output_type = Any
+        else:
+            output_type = NoneType
+        schema.append(SelectSchema(plan.output_name, SELECT_KIND_NAMES[plan.kind], output_type))
+    return schema
+
+cdef dict explain_field(FieldPlan field):
+    return {
+        "name": field.name,
+        "keys": (field.select_attribute,) + field.fallback_attributes,
+        "column_index": field.column_index,
+        "output_key": field.output_key,
+        "is_json": field.is_json,
+        "is_msgpack": field.is_msgpack,
+        "json_path": None if field.json_path is None else ".".join(field.json_path),
+        "codec": CODEC_NAMES.get(field.codec),
+        "element_codec": CODEC_NAMES.get(field.element_codec),
+        "submodel": field.submodel,
+        "coercions": tuple(COERCION_NAMES[step] for step in field.coercions),
+        "non_null": field.non_null,
+        "transform": field.transform is not None,
+    }
+
+def optimize_exec_explain(
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> List[Dict[str, Any]]:
+    """
+    Describe how the select would be cast, without casting any rows, for debugging
+    unexpected results. Returns one dict per select, in output order, with its `position`
+    in each result row, its `kind` ("table", "column", "function", "alias", or "unknown"),
+    and its `name` in dict output. Every select also lists the `fields` it reads: each
+    model field of a table, or the single value of any other select, with the row `keys`
+    tried in order, the `column_index` read from positional rows, the `output_key`, the
+    JSON flags and `json_path`, the built-in `codec` (and `element_codec` for arrays) as
+    a name, or None when the value passes through, the pydantic `submodel` that parsed
+    JSON is hydrated into, the names of its JSON `coercions` in order, and whether it's
+    `non_null` or has a `transform`. The plan reflects the given options, so it accepts
+    the same options as `optimize_exec_casting`.
+
+    """
+    cdef CastingOptions casting_options = CastingOptions(**options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    cdef list explained = []
+    cdef list fields
+    cdef SelectPlan plan
+    cdef Py_ssize_t j
+
+    for j in range(len(plans)):
+        plan = plans[j]
+        if plan.kind == SELECT_TABLE:
+            fields = [explain_field(field) for field in plan.fields]
+        elif plan.kind == SELECT_UNKNOWN:
+            fields = []
+        else:
+            fields = [explain_field(plan.column)]
+        explained.append({
+            "position": j,
+            "kind": SELECT_KIND_NAMES[plan.kind],
+            "name": plan.output_name,
+            "fields": fields,
+        })
+    return explained
+
+def optimize_exec_typed(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Tuple[List[Any], List[SelectSchema]]:
+    """
+    Cast the rows along with a description of their shape, for tooling that builds typed
+    wrappers or serializers around the results. The schema has one `SelectSchema` entry of
+    `(name, kind, type)` per select, where `kind` is "table", "column", "function", or "alias",
+    `name` is its key in dict output, and `type` is the model class, the column annotation, or
+    the alias type. Function results aren't typed at runtime, so they're reported as `Any`. The
+    schema is derived once from the plan, so it's the same for an empty result. Accepts the same
+    options as `optimize_exec_casting`, apart from `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_typed", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    return process_values(values, plans, casting_options), select_schema(plans)
+
+def optimize_exec_count(values: Iterable[Any], predicate: Any = None) -> int:
+    """
+    Count the raw database rows, for `.count()` style operations that never need the rows
+    themselves. With a `predicate`, only the rows it returns a truthy value for are counted.
+    It's called with each raw row, so nothing is cast either way.
+
+    """
+    cdef Py_ssize_t count = 0
+    cdef object value
+
+    if predicate is None:
+        if isinstance(values, list):
+            return len(values)
+        for _ in values:
+            count += 1
+        return count
+
+    for value in values:
+        if predicate(value):
+            count += 1
+    return count
+
+cdef FieldPlan resolve_map_key(list plans, str key_field):
+    """
+    Find the value that `optimize_exec_map` keys rows by. Matches the keys of `transforms`,
+    as well as bare field and column names when only one select has them.
+
+    """
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef list matches = []
+
+    for plan in plans:
+        if plan.kind == SELECT_TABLE:
+            for field in plan.fields:
+                if key_field == field.name or key_field == f"{plan.output_name}_{field.name}":
+                    matches.append(field)
+        elif plan.kind == SELECT_COLUMN:
+            if key_field == plan.column.name or key_field == (
+                f"{plan.select_raw.root_model.get_table_name()}_{plan.select_raw.key}"
+            ):
+                matches.append(plan.column)
+        elif plan.kind != SELECT_UNKNOWN and key_field == plan.column.name:
+            matches.append(plan.column)
+
+    if not matches:
+        raise ValueError(f"key_field '{key_field}' doesn't match any selected field")
+    if len(matches) > 1:
+        raise ValueError(
+            f"key_field '{key_field}' matches {len(matches)} selected fields, qualify it with "
+            "the table name, like 'userdemo_id'"
+        )
+    return matches[0]
+
+cdef object read_map_key(object cast, SelectPlan plan, FieldPlan key_plan, CastingOptions options):
+    """
+    Read the key back out of the value its select was cast to, so it's only fetched once and
+    has the type the results hold.
+
+    """
+    if plan.kind != SELECT_TABLE:
+        return cast
+    # Every field of an entirely NULL table is None
+    if cast is None:
+        return None
+    # Dict output and typed dicts keep the keys they were collected under
+    if isinstance(cast, dict):
+        if options.validate and options.output_mode == OUTPUT_MODEL:
+            return (<dict>cast).get(key_plan.init_name)
+        return (<dict>cast).get(key_plan.output_key)
+    return getattr(cast, key_plan.name)
+
+cdef dict process_mapped(
+    object values,
+    list plans,
+    FieldPlan key_plan,
+    str key_field,
+    str on_duplicate_key,
+    CastingOptions options,
+):
+    """
+    Same row loop as `process_values`, filing each row under the key that `read_map_key`
+    finds in the selects it was built from.
+
+    """
+    cdef dict mapped = {}
+    cdef list result_value = [None] * len(plans)
+    cdef SelectPlan key_select = plans[key_plan.select_index]
+    cdef list batched_fields = []
+    cdef list group
+    cdef object row
+    cdef object key
+    cdef Py_ssize_t i = 0
+    cdef object value
+
+    if isinstance(values, list) and options.batch_json:
+        batched_fields = parse_json_batches(values, plans, options)
+    try:
+        for value in values:
+            check_signals(i)
+            row = process_row(value, plans, result_value, i, options)
+            key = read_map_key(result_value[key_plan.select_index], key_select, key_plan, options)
+            if on_duplicate_key == "list":
+                group = mapped.get(key)
+                if group is None:
+                    mapped[key] = [row]
+                else:
+                    group.append(row)
+            elif on_duplicate_key == "error" and key in mapped:
+                raise ValueError(
+                    f"Duplicate key {key!r} for key_field '{key_field}' in row {i} "
+                    f"(select index {key_plan.select_index})"
+                )
+            else:
+                mapped[key] = row
+            i += 1
+    finally:
+        for field in batched_fields:
+            (<FieldPlan>field).batch_values = None
+    return mapped
+
+def optimize_exec_map(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    key_field: str,
+    on_duplicate_key: str = "last",
+    **options: Any,
+) -> Dict[Any, Any]:
+    """
+    Cast the rows into a dict keyed by one of their values, like `{user.id: user}`, for
+    callers that look rows up by key. `key_field` is a model field or column name, or the
+    select's function local name or alias name, and is qualified like the keys of
+    `transforms` (`userdemo_id`) when several selects share it. The key is cast like the
+    value itself, so it has the same type as the field on the results.
+
+    `on_duplicate_key` decides what happens when rows share a key: "last" (the default) keeps
+    the later row, "error" raises a ValueError naming the row, and "list" maps every key to the
+    list of its rows, in order, even when there's just one. Accepts the same options as
+    `optimize_exec_casting`, apart from `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_map", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    cdef FieldPlan key_plan = resolve_map_key(plans, key_field)
+
+    if on_duplicate_key not in ("last", "error", "list"):
+        raise ValueError(
+            f"Unknown on_duplicate_key '{on_duplicate_key}', expected one of "
+            "['last', 'error', 'list']"
+        )
+    return process_mapped(values, plans, key_plan, key_field, on_duplicate_key, casting_options)
+
+def optimize_exec_columnar(
+    values: List[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Dict[str, List[Any]]:
+    """
+    Cast raw database rows into a mapping of column name to the list of that column's values,
+    ready to hand to `pandas.DataFrame`. Table selects contribute one column per field. Accepts
+    the same options as `optimize_exec_casting`, apart from `collect_timings`,
+    `track_provenance`, and `output_mode`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_columnar", options)
+    # Tables are flattened into their fields, so collect them as dicts instead of models
+    casting_options.output_mode = OUTPUT_DICT
+    casting_options.flatten_tables = True
+    return process_columnar(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        casting_options,
+    )
+
+def optimize_exec_iter(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    **options: Any,
+) -> Iterator[Any]:
+    """
+    Iterator version of `optimize_exec_casting`. Rows are pulled from `values` and cast as the
+    iterator is consumed. Accepts the same options as `optimize_exec_casting`, apart from
+    `collect_timings` and `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_iter", options)
+    return CastingIterator(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        casting_options,
+    )
+
+def optimize_exec_chunked(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    chunk_size: int,
+    progress: Any = None,
+    **options: Any,
+) -> List[Any]:
+    """
+    Cast every row like `optimize_exec_casting`, calling `progress(rows_done, total)` after each
+    chunk of `chunk_size` rows so long loads can drive a progress bar or checkpoint. `total` is
+    the number of rows for lists and None for other iterables. The full result is returned once
+    every chunk is done. Accepts the same options as `optimize_exec_casting`, apart from
+    `collect_timings` and `track_provenance`.
+
+    """
+    if chunk_size < 1:
+        raise ValueError(f"chunk_size must be at least 1, got {chunk_size}")
+
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_chunked", options)
+    return process_chunked(
+        values,
+        plan_select(select_raws, select_types, casting_options),
+        chunk_size,
+        progress,
+        casting_options,
+    )
+
+def optimize_exec_grouped(
     values: List[Any],
     select_raws: List[Any],
-    select_types: List[Tuple[bool, bool, bool]]
+    select_types: List[Tuple[bool, bool, bool]],
+    group_by: Any,
+    relationship: str,
+    **options: Any,
 ) -> List[Any]:
-    return optimize_casting(values, select_raws, select_types)
+    """
+    Cast the rows of a one-to-many join, like `select((User, Post))` over `User LEFT JOIN Post`,
+    into one parent object per group. Consecutive rows that share the `group_by` value (usually
+    the parent's primary key column) collapse into a single parent whose `relationship` field
+    holds the list of its child objects. Parents without any matching children get an empty list.
+
+    `group_by` is a column of the parent, a tuple of its columns, or the parent table itself to
+    group by all of its primary key fields. Multi-column keys only match when every column does.
+
+    The select must contain exactly the parent and child tables, and `values` must be ordered by
+    the `group_by` column: a parent key that reappears later starts a new group. Accepts the
+    same options as `optimize_exec_casting`, apart from `collect_timings` and
+    `track_provenance`.
+
+    """
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_grouped", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    cdef SelectPlan parent_plan = None
+    cdef SelectPlan child_plan = None
+    cdef SelectPlan plan
+    cdef FieldPlan field
+    cdef FieldPlan key_field
+    cdef list key_fields = []
+    cdef object parent_model
+    cdef object column
+    cdef tuple group_columns = ()
+
+    if casting_options.output_mode == OUTPUT_NAMEDTUPLE:
+        # The parent's namedtuple has no slot for the relationship
+        raise ValueError("Grouped casting supports the model and dict output modes")
+
+    if len(plans) != 2:
+        raise ValueError("Grouped casting requires selecting exactly a parent and a child table")
+
+    if isclass(group_by) and issubclass(group_by, TableBase):
+        parent_model = group_by
+    else:
+        group_columns = tuple(group_by) if isinstance(group_by, (tuple, list)) else (group_by,)
+        parent_model = group_columns[0].root_model
+        for column in group_columns:
+            if column.root_model is not parent_model:
+                raise ValueError("group_by columns must all belong to the same table")
+
+    for plan in plans:
+        if plan.kind != SELECT_TABLE:
+            raise ValueError("Grouped casting requires selecting exactly a parent and a child table")
+        if plan.select_raw is parent_model:
+            parent_plan = plan
+        else:
+            child_plan = plan
+
+    if parent_plan is None or child_plan is None:
+        raise ValueError(
+            f"group_by table '{parent_model.get_table_name()}' is not one of the selected tables"
+        )
+
+    if not group_columns:
+        key_fields = primary_key_fields(parent_plan)
+        if not key_fields:
+            raise ValueError(
+                f"group_by table '{parent_model.get_table_name()}' has no primary key"
+            )
+    for column in group_columns:
+        key_field = None
+        for field in parent_plan.fields:
+            if field.name == column.key:
+                key_field = field
+                break
+        if key_field is None:
+            raise ValueError(f"group_by column '{column.key}' is not a selectable field")
+        key_fields.append(key_field)
+
+    # The parent's dict collects children across rows, so it can't be shared between groups
+    parent_plan.kwargs_buffer = None
+
+    return process_grouped(
+        values,
+        parent_plan,
+        child_plan,
+        key_fields,
+        relationship,
+        casting_options,
+    )
+
+def optimize_exec_json(
+    values: Iterable[Any],
+    select_raws: List[Any],
+    select_types: List[Tuple[bool, bool, bool]],
+    dumps: Any = None,
+    **options: Any,
+) -> List[str]:
+    """
+    Cast raw database rows straight into one JSON document per row, for handlers that would
+    otherwise build each model only to call `model_dump_json` on it. Rows are shaped like the
+    "dict" output mode, and `is_json` columns are embedded as parsed structures rather than
+    as escaped strings.
+
+    `dumps` serializes each row and defaults to the stdlib `json.dumps`, with values that JSON
+    has no native type for (datetimes, UUIDs, decimals, enums) converted like pydantic's JSON
+    mode. Accepts the same options as `optimize_exec_casting`, apart from `collect_timings`,
+    `track_provenance`, and `output_mode`.
+
+    """
+    if options.get("output_mode", "dict") != "dict":
+        raise ValueError("JSON casting always shapes rows like the dict output mode")
+    options["output_mode"] = "dict"
+
+    cdef CastingOptions casting_options = entrypoint_options("optimize_exec_json", options)
+    cdef list plans = plan_select(select_raws, select_types, casting_options)
+    cdef list rows = process_values(values, plans, casting_options)
+    cdef Py_ssize_t i
+
+    if dumps is None:
+        for i in range(len(rows)):
+            rows[i] = stdlib_json_dumps(rows[i], default=to_jsonable_python)
+    else:
+        for i in range(len(rows)):
+            rows[i] = dumps(rows[i])
+    return rows
+
+cdef class PreparedSelect:
+    """
+    A select whose plan has been resolved once, for query shapes that run repeatedly like
+    paginated fetches. `materialize` casts each batch of rows exactly like
+    `optimize_exec_casting`, without rebuilding the field plans on every call. Accepts the same
+    options as `optimize_exec_casting`, fixed at construction time. Each batch warns about
+    missing keys and collects timings on its own, and since the plans are already built, its
+    "plan_build" timing is always 0.
+
+    """
+    cdef list plans
+    cdef CastingOptions options
+
+    def __init__(
+        self,
+        select_raws: List[Any],
+        select_types: List[Tuple[bool, bool, bool]],
+        **options: Any,
+    ):
+        self.options = CastingOptions(**options)
+        self.plans = plan_select(select_raws, select_types, self.options)
+
+    def materialize(self, values: Iterable[Any]) -> Any:
+        cdef SelectPlan plan
+        # Objects are only shared within a batch, so the cache doesn't grow across pages
+        for plan in self.plans:
+            if plan.instances is not None:
+                plan.instances.clear()
+        self.options.reset_call_state()
+        return process_reported(values, self.plans, self.options, 0)
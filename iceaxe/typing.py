@@ -13,7 +13,7 @@ from typing import (
 from uuid import UUID
 
 if TYPE_CHECKING:
-    from iceaxe.alias_values import Alias
+    from iceaxe.alias_values import Alias, TableAlias
     from iceaxe.base import (
         DBFieldClassDefinition,
         TableBase,
@@ -67,6 +67,12 @@ def is_alias(obj: Any) -> TypeGuard[Alias]:
     return isinstance(obj, Alias)
 
 
+def is_table_alias(obj: Any) -> TypeGuard[TableAlias]:
+    from iceaxe.alias_values import TableAlias
+
+    return isinstance(obj, TableAlias)
+
+
 def column(obj: T) -> DBFieldClassDefinition[T]:
     if not is_column(obj):
         raise ValueError(f"Invalid column: {obj}")